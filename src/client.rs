@@ -2,11 +2,22 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+mod codec;
+mod config;
+mod http;
 mod kinto_http;
+#[cfg(feature = "write_api")]
+pub mod kinto_write;
+mod metrics;
+mod migrations;
 mod signatures;
+#[cfg(feature = "write_api")]
+pub mod signer_workflow;
 mod storage;
+#[cfg(test)]
+mod testing;
 
-use log::{debug, info};
+use log::{debug, info, warn};
 use std::collections::HashMap;
 use std::time::Duration;
 
@@ -16,14 +27,30 @@ use mock_instant::Instant;
 #[cfg(not(test))]
 use std::time::Instant;
 
+use hmac::{Hmac, Mac, NewMac};
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 use thiserror::Error;
 
-use kinto_http::{get_changeset, get_latest_change_timestamp, KintoError, KintoObject};
+use codec::{decode_collection, encode_collection, stored_checksum};
+pub use codec::{Codec, JsonCodec};
+pub use config::{ClientConfig, ConfigError};
+pub use http::{HttpClient, RequestOptions, ViaductHttpClient};
+pub use kinto_http::{
+    get_bucket, get_collection, get_record, get_server_info, list_buckets, list_collections,
+    list_records, Alert, AttachmentsCapability, Capabilities, ChangesCapability, ErrorResponse,
+    KintoError, KintoObject, ListParams, ListResponse, ServerInfo, SignerCapability,
+};
+use kinto_http::{
+    get_changeset, get_latest_change_timestamp, CacheValidators, Changeset, LatestChanges,
+};
+pub use metrics::{Metrics, NoopMetrics};
+use signatures::epoch_seconds;
 pub use signatures::{SignatureError, Verification};
 pub use storage::{
-    dummy_storage::DummyStorage, file_storage::FileStorage, memory_storage::MemoryStorage, Storage,
-    StorageError,
+    bounded_storage::BoundedStorage, dummy_storage::DummyStorage, file_storage::FileStorage,
+    memory_storage::MemoryStorage, record_storage::RecordStorage, tiered_storage::TieredStorage,
+    CollectionStorage, Storage, StorageError,
 };
 
 #[cfg(feature = "ring_verifier")]
@@ -32,6 +59,18 @@ pub use crate::client::signatures::ring_verifier::RingVerifier;
 #[cfg(feature = "rc_crypto_verifier")]
 pub use crate::client::signatures::rc_crypto_verifier::RcCryptoVerifier;
 
+#[cfg(feature = "cbor")]
+pub use crate::client::codec::CborCodec;
+
+#[cfg(feature = "sqlite_storage")]
+pub use crate::client::storage::sqlite_storage::SqliteStorage;
+
+#[cfg(feature = "compression")]
+pub use crate::client::storage::compressed_storage::CompressedStorage;
+
+#[cfg(feature = "encryption")]
+pub use crate::client::storage::encrypted_storage::{Cipher, EncryptedStorage, KeyProvider};
+
 use crate::client::signatures::dummy_verifier::DummyVerifier;
 
 pub const DEFAULT_SERVER_URL: &str = "https://firefox.settings.services.mozilla.com/v1";
@@ -39,6 +78,9 @@ pub const DEFAULT_BUCKET_NAME: &str = "main";
 pub const DEFAULT_SIGNER_NAME: &str = "remote-settings.content-signature.mozilla.org";
 pub const PROD_CERT_ROOT_HASH: &str = "97:E8:BA:9C:F1:2F:B3:DE:53:CC:42:A4:E6:57:7E:D6:4D:F4:93:C2:47:B4:14:FE:A0:36:81:8D:38:23:56:0E";
 
+// Verified content is checked again when its certificates are about to expire.
+const VERIFIED_CONTENT_EXPIRY_MARGIN_SECS: u64 = 24 * 60 * 60;
+
 #[derive(Debug, Error)]
 pub enum ClientError {
     #[error("content signature could not be verified: {0}")]
@@ -113,6 +155,83 @@ pub struct Collection {
     pub signer: String,
 }
 
+/// A callback for the [`Alert`]s sent by the server (eg. deprecation or end-of-service).
+///
+/// It receives the alerts of every response (`monitor/changes`, changesets, server root),
+/// including `304 Not Modified` ones. Alerts are always logged as warnings. Any closure taking an `&Alert` can be used.
+///
+/// # Examples
+/// ```rust
+/// # use remote_settings_client::{Alert, Client};
+/// let client = Client::builder()
+///   .collection_name("cid")
+///   .alert_handler(Box::new(|alert: &Alert| eprintln!("Remote Settings: {:?}", alert)))
+///   .build()
+///   .unwrap();
+/// ```
+pub trait AlertHandler: Send + Sync {
+    fn on_alert(&self, alert: &Alert);
+}
+
+impl<F> AlertHandler for F
+where
+    F: Fn(&Alert) + Send + Sync,
+{
+    fn on_alert(&self, alert: &Alert) {
+        self(alert)
+    }
+}
+
+/// A callback for the local data found corrupted (ie. whose checksum does not match).
+///
+/// Corrupted data is always logged as a warning, and treated as empty. Any closure taking
+/// the storage key and the error can be used.
+///
+/// Collections stored as values, and the entries of [`RecordStorage`], have a checksum.
+/// `SqliteStorage` has none, and relies on the integrity checks of SQLite.
+///
+/// # Examples
+/// ```rust
+/// # use remote_settings_client::{Client, StorageError};
+/// let client = Client::builder()
+///   .collection_name("cid")
+///   .corruption_handler(Box::new(|key: &str, err: &StorageError| {
+///     eprintln!("Remote Settings: {} {}", key, err)
+///   }))
+///   .build()
+///   .unwrap();
+/// ```
+pub trait CorruptionHandler: Send {
+    fn on_corruption(&self, key: &str, error: &StorageError);
+}
+
+impl<F> CorruptionHandler for F
+where
+    F: Fn(&str, &StorageError) + Send,
+{
+    fn on_corruption(&self, key: &str, error: &StorageError) {
+        self(key, error)
+    }
+}
+
+/// A secret key (eg. from the OS keychain) authenticating the verifications persisted in the
+/// local storage, so that content verified by a previous client is not verified again.
+///
+/// Without it, verifications are only remembered by the client that performed them, since
+/// anyone able to write the storage could otherwise mark tampered data as verified.
+///
+/// A verification is stored in the storage of the client, next to the collection, under
+/// its key suffixed with `:verified` (8 bytes of expiry followed by the MAC). Storages
+/// handle it as any other entry: it can be evicted (eg. by a [`BoundedStorage`]) and is not
+/// deleted along with the collection, in which case the content is simply verified again.
+pub struct VerifiedContentKey(pub [u8; 32]);
+
+impl std::fmt::Debug for VerifiedContentKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "VerifiedContentKey(..)")
+    }
+}
+
 /// Client to fetch Remote Settings data.
 ///
 /// # Examples
@@ -187,7 +306,7 @@ pub struct Collection {
 /// See [`Verification`] for implementing a custom signature verifier.
 ///
 #[derive(Builder, Debug)]
-#[builder(pattern = "owned")] // No clone because of Box<dyn...>
+#[builder(pattern = "owned", build_fn(validate = "Self::validate"))] // No clone because of Box<dyn...>
 pub struct Client {
     #[builder(setter(into), default = "DEFAULT_SERVER_URL.to_owned()")]
     server_url: String,
@@ -202,12 +321,34 @@ pub struct Client {
     verifier: Box<dyn Verification>,
     #[builder(default = "Box::new(DummyStorage {})")]
     storage: Box<dyn Storage>,
+    #[builder(default = "Box::new(JsonCodec {})")]
+    codec: Box<dyn Codec>,
+    #[builder(default = "Box::new(ViaductHttpClient {})")]
+    http_client: Box<dyn HttpClient>,
+    #[builder(setter(custom), default = "RequestOptions::default()")]
+    request_options: RequestOptions,
+    #[builder(setter(strip_option), default = "None")]
+    alert_handler: Option<Box<dyn AlertHandler>>,
+    #[builder(setter(strip_option), default = "None")]
+    corruption_handler: Option<Box<dyn CorruptionHandler>>,
+    #[builder(default = "Box::new(NoopMetrics {})")]
+    metrics: Box<dyn Metrics>,
     #[builder(default = "true")]
     sync_if_empty: bool,
     #[builder(default = "true")]
     trust_local: bool,
+    #[builder(setter(strip_option), default = "None")]
+    verified_content_key: Option<VerifiedContentKey>,
     #[builder(private, default = "None")]
     backoff_until: Option<Instant>,
+    #[builder(private, default = "LatestChanges::default()")]
+    latest_changes: LatestChanges,
+    #[builder(private, default = "None")]
+    changeset_validators: Option<(u64, u64, CacheValidators)>,
+    #[builder(private, default = "None")]
+    server_info: Option<ServerInfo>,
+    #[builder(private, default = "None")]
+    verified_content: Option<([u8; 32], u64)>,
     #[builder(default = "PROD_CERT_ROOT_HASH.to_owned()")]
     cert_root_hash: String,
 }
@@ -230,16 +371,140 @@ impl std::fmt::Debug for Box<dyn Storage> {
     }
 }
 
+impl std::fmt::Debug for Box<dyn Codec> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Box<dyn Codec>")
+    }
+}
+
+impl std::fmt::Debug for Box<dyn HttpClient> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Box<dyn HttpClient>")
+    }
+}
+
+impl std::fmt::Debug for Box<dyn AlertHandler> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Box<dyn AlertHandler>")
+    }
+}
+
+impl std::fmt::Debug for Box<dyn CorruptionHandler> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Box<dyn CorruptionHandler>")
+    }
+}
+
+impl std::fmt::Debug for Box<dyn Metrics> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Box<dyn Metrics>")
+    }
+}
+
+impl ClientBuilder {
+    /// Identify the consuming product in the `User-Agent` header of requests (eg. `"firefox/94.0"`).
+    pub fn user_agent<S: Into<String>>(mut self, user_agent: S) -> Self {
+        self.request_options_mut().user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Add a header to every request sent to the server.
+    pub fn header<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.request_options_mut()
+            .headers
+            .push((name.into(), value.into()));
+        self
+    }
+
+    /// Timeout for connecting to the server. See [`HttpClient::send_with_timeouts`].
+    ///
+    /// Not supported by the default [`ViaductHttpClient`].
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.request_options_mut().connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Timeout for reading the server response. See [`HttpClient::send_with_timeouts`].
+    ///
+    /// Not supported by the default [`ViaductHttpClient`].
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.request_options_mut().read_timeout = Some(timeout);
+        self
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let has_timeouts = match &self.request_options {
+            Some(options) => options.connect_timeout.is_some() || options.read_timeout.is_some(),
+            None => false,
+        };
+        // The default `ViaductHttpClient` does not support them.
+        let supports_timeouts = match &self.http_client {
+            Some(http) => http.supports_timeouts(),
+            None => false,
+        };
+        if has_timeouts && !supports_timeouts {
+            return Err("timeouts are not supported by the HTTP client".to_string());
+        }
+        Ok(())
+    }
+
+    fn request_options_mut(&mut self) -> &mut RequestOptions {
+        self.request_options
+            .get_or_insert_with(RequestOptions::default)
+    }
+}
+
 impl Client {
     /// Creates a `ClientBuilder` to configure a `Client`.
     pub fn builder() -> ClientBuilder {
         ClientBuilder::default()
     }
 
+    /// Key of the collection in the local storage.
+    ///
+    /// It is namespaced by the server origin and the root certificate hash, so that clients
+    /// of different servers (eg. prod and stage) can share the same storage.
     pub fn _storage_key(&self) -> String {
+        format!(
+            "{}/{}",
+            storage_namespace(&self.server_url, &self.cert_root_hash),
+            self._legacy_storage_key()
+        )
+    }
+
+    // Key of the verification of the collection content, next to the collection.
+    fn _verification_storage_key(&self) -> String {
+        format!(
+            "{}/{}/{}:verified",
+            storage_namespace(&self.server_url, &self.cert_root_hash),
+            self.bucket_name,
+            self.collection_name
+        )
+    }
+
+    // Key of the collection before storage keys were namespaced.
+    fn _legacy_storage_key(&self) -> String {
         format!("{}/{}:collection", self.bucket_name, self.collection_name)
     }
 
+    /// Return the information about the server (version, capabilities, end-of-service date).
+    ///
+    /// The server root URL is only fetched once, and its content is cached in the client.
+    ///
+    /// # Errors
+    /// If an error occurs while fetching the server root URL, a [`ClientError`] is returned.
+    pub fn server_info(&mut self) -> Result<&ServerInfo, ClientError> {
+        if self.server_info.is_none() {
+            let http = self
+                .request_options
+                .apply(self.http_client.as_ref())
+                .with_alert_handler(self.alert_handler.as_deref());
+            let info = get_server_info(&http, &self.server_url)?;
+            self.server_info = Some(info);
+        }
+        Ok(self.server_info.as_ref().unwrap())
+    }
+
     /// Return the records stored locally.
     ///
     /// # Examples
@@ -262,6 +527,14 @@ impl Client {
     /// * If local data is empty and if `sync_if_empty` is `true` (*default*),
     ///   then synchronize the local data with the server and return records, otherwise
     ///   return an error.
+    /// * Local data stored with a previous schema version is migrated. If it cannot be
+    ///   migrated (eg. newer schema version, or unknown codec), it is treated as empty. Other
+    ///   read errors (eg. I/O, or decryption failures) are returned, and local data is left as is.
+    /// * Local data whose checksum does not match is reported to the `corruption_handler`,
+    ///   and treated as empty.
+    /// * With `trust_local` as `false`, the signature of local data is verified, unless the
+    ///   same content was already verified by this client (or by a previous one, with the same
+    ///   `verified_content_key`) and its certificates are not about to expire.
     ///
     /// Note: with the [`DummyStorage`], any call to `.get()` will trigger a synchronization.
     ///
@@ -273,19 +546,13 @@ impl Client {
     pub fn get(&mut self) -> Result<Vec<Record>, ClientError> {
         let storage_key = self._storage_key();
 
-        debug!("Retrieve from storage with key={:?}", storage_key);
-        let read_result = self.storage.retrieve(&storage_key);
-
-        match read_result {
-            Ok(stored_bytes) => {
-                // Deserialize content of storage and surface error if fails.
-                let stored: Collection = serde_json::from_slice(&stored_bytes).map_err(|err| {
-                    StorageError::ReadError(format!("cannot deserialize collection: {}", err))
-                })?;
+        // Deserialize content of storage and surface error if fails.
+        match self.retrieve_or_migrate(&storage_key) {
+            Ok((stored, checksum)) => {
                 // Verify signature of stored data (*optional*)
                 if !self.trust_local {
                     debug!("Verify signature of local data.");
-                    self.verifier.verify(&stored, &self.cert_root_hash)?;
+                    self.verify(&stored, checksum)?;
                 }
 
                 Ok(stored.records)
@@ -296,6 +563,23 @@ impl Client {
                 let collection = self.sync(None)?;
                 Ok(collection.records)
             }
+            // If local data cannot be migrated (eg. newer schema version), start over.
+            Err(StorageError::UnsupportedSchema(reason)) if self.sync_if_empty => {
+                warn!(
+                    "Local data cannot be migrated ({}), synchronize again.",
+                    reason
+                );
+                let collection = self.sync(None)?;
+                Ok(collection.records)
+            }
+            // If local data is corrupted, discard it and start over (reported when retrieved).
+            Err(StorageError::CorruptedData(_)) if self.sync_if_empty => {
+                if let Err(err) = self.storage.delete(&storage_key) {
+                    warn!("Corrupted data could not be deleted ({}).", err);
+                }
+                let collection = self.sync(None)?;
+                Ok(collection.records)
+            }
             // Otherwise, surface the error.
             Err(err) => Err(err.into()),
         }
@@ -307,71 +591,188 @@ impl Client {
     /// * If stored data is up-to-date and signature of local data valid, then return local content;
     /// * Otherwise fetch content from server, merge with local content, verify signature, and return records;
     ///
+    /// Requests to the server are conditional (`If-None-Match`): if the `monitor/changes` endpoint
+    /// or the changeset were not modified since the previous call, they are not downloaded again.
+    ///
     /// # Errors
     /// If an error occurs while fetching or verifying records, a [`ClientError`] is returned.
     pub fn sync<T>(&mut self, expected: T) -> Result<Collection, ClientError>
     where
         T: Into<Option<u64>>,
     {
-        self.check_sync_state()?;
+        self.metrics
+            .sync_started(&self.bucket_name, &self.collection_name);
+        let started = Instant::now();
+
+        let result = self.sync_collection(expected.into());
+
+        self.metrics.sync_finished(
+            &self.bucket_name,
+            &self.collection_name,
+            started.elapsed(),
+            result.as_ref().map(|_| ()),
+        );
+        result
+    }
 
+    /// Delete the local data of this collection (eg. when it is no longer used).
+    ///
+    /// A subsequent `.get()` or `.sync()` downloads the whole collection again.
+    ///
+    /// # Errors
+    /// If an error occurs while deleting, a [`ClientError::StorageError`] is returned.
+    pub fn purge(&mut self) -> Result<(), ClientError> {
         let storage_key = self._storage_key();
+        debug!("Delete local data with key={:?}", storage_key);
+        self.storage.delete(&storage_key)?;
+        self.storage.delete(&self._verification_storage_key())?;
+        if self.is_default_server() {
+            self.storage.delete(&self._legacy_storage_key())?;
+        }
+        self.changeset_validators = None;
+        Ok(())
+    }
 
-        debug!("Retrieve from storage with key={:?}", storage_key);
-        let stored_bytes: Vec<u8> = self.storage.retrieve(&storage_key).unwrap_or_default();
-        let stored: Option<Collection> = serde_json::from_slice(&stored_bytes).unwrap_or(None);
+    /// Delete everything from the local storage, including the data of other collections
+    /// sharing the same storage (eg. on a privacy reset).
+    ///
+    /// # Errors
+    /// If an error occurs while deleting, a [`ClientError::StorageError`] is returned.
+    pub fn clear_local(&mut self) -> Result<(), ClientError> {
+        info!("Clear local storage");
+        self.storage.clear()?;
+        self.changeset_validators = None;
+        Ok(())
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            name = "sync",
+            skip(self),
+            fields(
+                bucket = %self.bucket_name,
+                collection = %self.collection_name,
+                local_timestamp = tracing::field::Empty,
+                remote_timestamp = tracing::field::Empty,
+            ),
+            err
+        )
+    )]
+    fn sync_collection(&mut self, expected: Option<u64>) -> Result<Collection, ClientError> {
+        self.check_sync_state()?;
 
-        let remote_timestamp = match expected.into() {
+        let storage_key = self._storage_key();
+        let (mut stored, stored_checksum) = match self.read_stored(&storage_key) {
+            Some((collection, checksum)) => (Some(collection), checksum),
+            None => (None, None),
+        };
+
+        let remote_timestamp = match expected {
             Some(v) => v,
             None => {
                 debug!("Obtain current timestamp.");
-                get_latest_change_timestamp(
+                let http = self
+                    .request_options
+                    .apply(self.http_client.as_ref())
+                    .with_alert_handler(self.alert_handler.as_deref());
+                let timestamp = get_latest_change_timestamp(
+                    &http,
                     &self.server_url,
                     &self.bucket_name,
                     &self.collection_name,
-                )?
+                    &mut self.latest_changes,
+                )?;
+                self.enter_backoff(self.latest_changes.backoff());
+                timestamp
             }
         };
+        record_span_field!("remote_timestamp", remote_timestamp);
 
         if let Some(ref collection) = stored {
             let up_to_date = collection.timestamp == remote_timestamp;
-            if up_to_date
-                && self
-                    .verifier
-                    .verify(&collection, &self.cert_root_hash)
-                    .is_ok()
-            {
+            if up_to_date && self.verify(collection, stored_checksum).is_ok() {
                 debug!("Local data is up-to-date and valid.");
                 return Ok(stored.unwrap());
             }
         }
 
         info!("Local data is empty, outdated, or has been tampered. Fetch from server.");
-        let (local_records, local_timestamp) = match stored {
-            Some(c) => (c.records, Some(c.timestamp)),
-            None => (Vec::new(), None),
+        let mut local_timestamp = stored.as_ref().map(|c| c.timestamp);
+        if let Some(timestamp) = local_timestamp {
+            record_span_field!("local_timestamp", timestamp);
+        }
+
+        // The validators of the last stored changeset are only sent when the local data
+        // is the result of this changeset, to fall back on if the server content was not modified.
+        let mut validators = match (&stored, &self.changeset_validators) {
+            (Some(_), Some((expected, stored_timestamp, validators)))
+                if *expected == remote_timestamp && Some(*stored_timestamp) == local_timestamp =>
+            {
+                Some(validators)
+            }
+            _ => None,
         };
 
-        let changeset = get_changeset(
-            &self.server_url,
-            &self.bucket_name,
-            &self.collection_name,
-            remote_timestamp,
-            local_timestamp,
-        )?;
-
-        // Keep in state that the server indicated the client
-        // to backoff for a while.
-        if let Some(backoff_secs) = changeset.backoff {
-            self.backoff_until = Some(Instant::now() + Duration::from_secs(backoff_secs));
-        }
+        let changeset = loop {
+            let http = self
+                .request_options
+                .apply(self.http_client.as_ref())
+                .with_alert_handler(self.alert_handler.as_deref());
+            match get_changeset(
+                &http,
+                &self.server_url,
+                &self.bucket_name,
+                &self.collection_name,
+                remote_timestamp,
+                local_timestamp,
+                validators,
+            )? {
+                Changeset::Modified(changeset) => break changeset,
+                Changeset::NotModified { backoff } => {
+                    self.enter_backoff(backoff);
+                    let collection = stored.take().unwrap(); // Validators are only sent with local data.
+                    if self.verify(&collection, stored_checksum).is_ok() {
+                        debug!("Server content was not modified and local data is valid.");
+                        return Ok(collection);
+                    }
+                    debug!("Server content was not modified but local data is invalid. Fetch it entirely.");
+                    self.changeset_validators = None;
+                    local_timestamp = None;
+                    validators = None;
+                }
+            }
+        };
+        let changeset_validators = changeset.validators;
+        self.metrics
+            .changeset_downloaded(&self.bucket_name, &self.collection_name, changeset.size);
+
+        // Storages with record-level updates only write the changes to the local data.
+        let changes = match (&stored, self.storage.as_collection_storage()) {
+            (Some(_), Some(_)) => Some(
+                changeset
+                    .changes
+                    .iter()
+                    .cloned()
+                    .map(Record::new)
+                    .collect::<Vec<Record>>(),
+            ),
+            _ => None,
+        };
+        let local_records = stored.map(|c| c.records).unwrap_or_default();
+
+        self.enter_backoff(changeset.backoff);
 
         debug!(
             "Apply {} changes to {} local records",
             changeset.changes.len(),
             local_records.len()
         );
+        let changes_count = changeset.changes.len();
         let merged = merge_changes(local_records, changeset.changes);
+        self.metrics
+            .changes_applied(&self.bucket_name, &self.collection_name, changes_count);
 
         let collection = Collection {
             bid: self.bucket_name.clone(),
@@ -383,19 +784,208 @@ impl Client {
         };
 
         debug!("Verify signature after merge of changes with previous local data.");
-        self.verifier.verify(&collection, &self.cert_root_hash)?;
+        self.verify(&collection, None)?;
 
-        debug!("Store collection with key={:?}", storage_key);
-        let collection_bytes: Vec<u8> = serde_json::to_string(&collection)
-            .map_err(|err| {
-                StorageError::WriteError(format!("cannot serialize collection: {}", err))
-            })?
-            .into();
-        self.storage.store(&storage_key, collection_bytes)?;
+        self.store_collection(&storage_key, &collection, changes.as_deref())?;
+
+        self.changeset_validators = if changeset_validators.is_empty() {
+            None
+        } else {
+            Some((remote_timestamp, collection.timestamp, changeset_validators))
+        };
 
         Ok(collection)
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    fn read_stored(&mut self, storage_key: &str) -> Option<(Collection, Option<[u8; 32]>)> {
+        self.retrieve_or_migrate(storage_key).ok()
+    }
+
+    /// Retrieve the collection, or move it from its legacy key if it was stored before keys
+    /// were namespaced. Since namespaces did not exist, legacy data is assumed to come from
+    /// the default server, and is left aside for other servers.
+    fn retrieve_or_migrate(
+        &mut self,
+        storage_key: &str,
+    ) -> Result<(Collection, Option<[u8; 32]>), StorageError> {
+        match self.retrieve_collection(storage_key) {
+            Err(StorageError::KeyNotFound { key }) if self.is_default_server() => {
+                let legacy_key = self._legacy_storage_key();
+                let collection = match self.retrieve_collection(&legacy_key) {
+                    Ok((collection, _)) => collection,
+                    Err(_) => return Err(StorageError::KeyNotFound { key }),
+                };
+                info!("Move local data from {:?} to {:?}", legacy_key, storage_key);
+                self.store_collection(storage_key, &collection, None)?;
+                if let Err(err) = self.storage.delete(&legacy_key) {
+                    // Left aside, the legacy data is not read again.
+                    warn!("Legacy data could not be deleted ({}).", err);
+                }
+                Ok((collection, None))
+            }
+            result => result,
+        }
+    }
+
+    fn is_default_server(&self) -> bool {
+        storage_namespace(&self.server_url, &self.cert_root_hash)
+            == storage_namespace(DEFAULT_SERVER_URL, PROD_CERT_ROOT_HASH)
+    }
+
+    /// Retrieve the collection, and the checksum of its stored form if it has one.
+    fn retrieve_collection(
+        &self,
+        storage_key: &str,
+    ) -> Result<(Collection, Option<[u8; 32]>), StorageError> {
+        debug!("Retrieve from storage with key={:?}", storage_key);
+        let result = match self.storage.as_collection_storage() {
+            Some(storage) => storage
+                .retrieve_collection(storage_key, self.codec.as_ref())
+                .map(|collection| (collection, None)),
+            None => self.storage.retrieve(storage_key).and_then(|stored_bytes| {
+                let collection = decode_collection(self.codec.as_ref(), &stored_bytes)?;
+                Ok((collection, stored_checksum(&stored_bytes)))
+            }),
+        };
+        // Reported whichever the storage, as long as it detects corruption (eg. with checksums).
+        if let Err(err @ StorageError::CorruptedData(_)) = &result {
+            warn!(
+                "Local data with key={:?} is corrupted: {}",
+                storage_key, err
+            );
+            if let Some(handler) = &self.corruption_handler {
+                handler.on_corruption(storage_key, err);
+            }
+        }
+        result
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self, collection, changes), err)
+    )]
+    fn store_collection(
+        &mut self,
+        storage_key: &str,
+        collection: &Collection,
+        changes: Option<&[Record]>,
+    ) -> Result<(), StorageError> {
+        debug!("Store collection with key={:?}", storage_key);
+        if let Some(storage) = self.storage.as_collection_storage_mut() {
+            return match changes {
+                Some(changes) => {
+                    storage.apply_changes(storage_key, collection, changes, self.codec.as_ref())
+                }
+                None => storage.store_collection(storage_key, collection, self.codec.as_ref()),
+            };
+        }
+        let collection_bytes = encode_collection(self.codec.as_ref(), collection)?;
+        self.storage.store(storage_key, collection_bytes)
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(timestamp = collection.timestamp),
+            err
+        )
+    )]
+    fn verify(
+        &mut self,
+        collection: &Collection,
+        checksum: Option<[u8; 32]>,
+    ) -> Result<(), SignatureError> {
+        // The content is identified by the checksum of its stored form, or of its encoded
+        // form for storages without one (eg. record-level storages).
+        let checksum = checksum.or_else(|| {
+            encode_collection(self.codec.as_ref(), collection)
+                .ok()
+                .and_then(|bytes| stored_checksum(&bytes))
+        });
+        // Skip the verification if the same content was verified with certificates still valid.
+        if let Some(checksum) = &checksum {
+            if self.is_verified(checksum) {
+                debug!("Content was already verified.");
+                return Ok(());
+            }
+        }
+
+        let http = self
+            .request_options
+            .apply(self.http_client.as_ref())
+            .with_alert_handler(self.alert_handler.as_deref());
+        let started = Instant::now();
+
+        let result = self
+            .verifier
+            .verify_with_expiry(&http, collection, &self.cert_root_hash);
+
+        self.metrics.verification_finished(
+            &self.bucket_name,
+            &self.collection_name,
+            started.elapsed(),
+            result.as_ref().map(|_| ()),
+        );
+        self.verified_content = match (checksum, &result) {
+            (Some(checksum), Ok(Some(expiry))) => Some((checksum, *expiry)),
+            _ => None,
+        };
+        if let Some((checksum, expiry)) = self.verified_content {
+            self.persist_verification(&checksum, expiry);
+        }
+        result.map(|_| ())
+    }
+
+    /// Whether the content was verified with certificates that are not about to expire.
+    fn is_verified(&self, checksum: &[u8; 32]) -> bool {
+        let expiry = match &self.verified_content {
+            Some((verified, expiry)) if verified == checksum => Some(*expiry),
+            _ => self.persisted_verification(checksum),
+        };
+        matches!(expiry, Some(expiry) if epoch_seconds() + VERIFIED_CONTENT_EXPIRY_MARGIN_SECS < expiry)
+    }
+
+    /// Return the expiry of the persisted verification of the content, if authenticated.
+    fn persisted_verification(&self, checksum: &[u8; 32]) -> Option<u64> {
+        let key = self.verified_content_key.as_ref()?;
+        let stored = self
+            .storage
+            .retrieve(&self._verification_storage_key())
+            .ok()?;
+        if stored.len() != 8 + 32 {
+            return None;
+        }
+        let (expiry, mac) = stored.split_at(8);
+        let mut expiry_bytes = [0; 8];
+        expiry_bytes.copy_from_slice(expiry);
+        // The comparison is made in constant time, so that timing does not reveal the MAC.
+        let expected = verification_mac(key, &self.cert_root_hash, checksum, expiry_bytes);
+        if expected.verify(mac).is_ok() {
+            Some(u64::from_be_bytes(expiry_bytes))
+        } else {
+            debug!("Persisted verification does not match the content.");
+            None
+        }
+    }
+
+    /// Store the verification of the content next to it, with its expiry and MAC.
+    fn persist_verification(&mut self, checksum: &[u8; 32], expiry: u64) {
+        let key = match &self.verified_content_key {
+            Some(key) => key,
+            None => return,
+        };
+        let mut value = expiry.to_be_bytes().to_vec();
+        let mac = verification_mac(key, &self.cert_root_hash, checksum, expiry.to_be_bytes());
+        value.extend_from_slice(&mac.finalize().into_bytes());
+        let storage_key = self._verification_storage_key();
+        if let Err(err) = self.storage.store(&storage_key, value) {
+            warn!("Verification could not be stored ({}).", err);
+        }
+    }
+
     fn check_sync_state(&mut self) -> Result<(), ClientError> {
         if let Some(until) = self.backoff_until {
             if Instant::now() < until {
@@ -406,8 +996,61 @@ impl Client {
         }
         Ok(())
     }
+
+    /// Keep in state that the server indicated the client to backoff for a while.
+    fn enter_backoff(&mut self, backoff_secs: Option<u64>) {
+        if let Some(backoff_secs) = backoff_secs {
+            let backoff = Duration::from_secs(backoff_secs);
+            self.backoff_until = Some(Instant::now() + backoff);
+            self.metrics
+                .backoff_entered(&self.bucket_name, &self.collection_name, backoff);
+        }
+    }
+}
+
+/// Return the MAC of the verification of the content with this root certificate hash,
+/// until the certificates expiry, to be finalized or verified.
+fn verification_mac(
+    key: &VerifiedContentKey,
+    cert_root_hash: &str,
+    checksum: &[u8; 32],
+    expiry: [u8; 8],
+) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(&key.0).expect("HMAC accepts any key size");
+    mac.update(cert_root_hash.as_bytes());
+    mac.update(b"|");
+    mac.update(checksum);
+    mac.update(&expiry);
+    mac
+}
+
+/// Return a short and stable identifier of the server origin and root certificate hash.
+fn storage_namespace(server_url: &str, cert_root_hash: &str) -> String {
+    let origin = match url::Url::parse(server_url) {
+        Ok(url) => url.origin().ascii_serialization(),
+        Err(_) => server_url.to_string(),
+    };
+    // 64 bits FNV-1a, whose output does not depend on the platform nor the Rust version.
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in origin
+        .bytes()
+        .chain(b"|".iter().copied())
+        .chain(cert_root_hash.bytes())
+    {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
 }
 
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(local = local_records.len(), changes = remote_changes.len())
+    )
+)]
 fn merge_changes(local_records: Vec<Record>, remote_changes: Vec<KintoObject>) -> Vec<Record> {
     // Merge changes by record id and delete tombstones.
     let mut local_by_id: HashMap<String, Record> = local_records
@@ -426,17 +1069,21 @@ fn merge_changes(local_records: Vec<Record>, remote_changes: Vec<KintoObject>) -
 
     local_by_id.into_iter().map(|(_, v)| v).collect()
 }
+
 #[cfg(test)]
 mod tests {
     use super::signatures::{SignatureError, Verification};
     use super::{
-        Client, ClientError, Collection, DummyStorage, DummyVerifier, MemoryStorage, Record,
+        Alert, Client, ClientError, Collection, DummyStorage, DummyVerifier, HttpClient,
+        MemoryStorage, Metrics, Record, RecordStorage, Storage, StorageError, TieredStorage,
+        VerifiedContentKey,
     };
     use env_logger;
     use httpmock::MockServer;
     use serde_json::json;
+    use std::sync::{Arc, Mutex};
     use std::time::Duration;
-    use viaduct::set_backend;
+    use viaduct::{set_backend, Headers, Request, Response};
     use viaduct_reqwest::ReqwestBackend;
 
     #[cfg(feature = "ring_verifier")]
@@ -457,13 +1104,41 @@ mod tests {
             Ok(()) // unreachable.
         }
 
-        fn verify(&self, _collection: &Collection, _: &str) -> Result<(), SignatureError> {
+        fn verify(
+            &self,
+            _: &dyn HttpClient,
+            _collection: &Collection,
+            _: &str,
+        ) -> Result<(), SignatureError> {
             Err(SignatureError::MismatchError(
                 "fake invalid signature".to_owned(),
             ))
         }
     }
 
+    struct FakeHttpClient {}
+
+    impl HttpClient for FakeHttpClient {
+        fn send(&self, request: Request) -> Result<Response, viaduct::Error> {
+            Ok(Response {
+                request_method: request.method,
+                url: request.url,
+                status: 200,
+                headers: Headers::new(),
+                body: json!({
+                    "metadata": {},
+                    "changes": [{
+                        "id": "record-1",
+                        "last_modified": 42
+                    }],
+                    "timestamp": 42
+                })
+                .to_string()
+                .into_bytes(),
+            })
+        }
+    }
+
     fn init() {
         let _ = env_logger::builder().is_test(true).try_init();
         let _ = set_backend(&ReqwestBackend);
@@ -490,7 +1165,7 @@ mod tests {
         assert_eq!(client.sync_if_empty, true);
         assert_eq!(client.trust_local, true);
         // And Debug format
-        assert_eq!(format!("{:?}", client), "Client { server_url: \"https://firefox.settings.services.mozilla.com/v1\", bucket_name: \"main\", collection_name: \"cid\", signer_name: \"remote-settings.content-signature.mozilla.org\", verifier: Box<dyn Verification>, storage: Box<dyn Storage>, sync_if_empty: true, trust_local: true, backoff_until: None, cert_root_hash: \"97:E8:BA:9C:F1:2F:B3:DE:53:CC:42:A4:E6:57:7E:D6:4D:F4:93:C2:47:B4:14:FE:A0:36:81:8D:38:23:56:0E\" }");
+        assert_eq!(format!("{:?}", client), "Client { server_url: \"https://firefox.settings.services.mozilla.com/v1\", bucket_name: \"main\", collection_name: \"cid\", signer_name: \"remote-settings.content-signature.mozilla.org\", verifier: Box<dyn Verification>, storage: Box<dyn Storage>, codec: Box<dyn Codec>, http_client: Box<dyn HttpClient>, request_options: RequestOptions { user_agent: None, headers: [], connect_timeout: None, read_timeout: None }, alert_handler: None, corruption_handler: None, metrics: Box<dyn Metrics>, sync_if_empty: true, trust_local: true, verified_content_key: None, backoff_until: None, latest_changes: LatestChanges { validators: CacheValidators { etag: None, last_modified: None }, timestamps: {}, backoff: None }, changeset_validators: None, server_info: None, verified_content: None, cert_root_hash: \"97:E8:BA:9C:F1:2F:B3:DE:53:CC:42:A4:E6:57:7E:D6:4D:F4:93:C2:47:B4:14:FE:A0:36:81:8D:38:23:56:0E\" }");
     }
 
     #[test]
@@ -637,143 +1312,309 @@ mod tests {
         let err = client.get().unwrap_err();
         assert_eq!(
             err.to_string(),
-            "storage I/O error: key could not be found: main/url-classifier-skip-urls:collection"
+            format!(
+                "storage I/O error: key could not be found: {}",
+                client._storage_key()
+            )
         );
+        assert!(client
+            ._storage_key()
+            .ends_with("/main/url-classifier-skip-urls:collection"));
     }
 
     #[test]
-    fn test_get_bad_stored_data() {
-        init();
-        let mock_server = MockServer::start();
-
-        let mut client = Client::builder()
-            .server_url(mock_server.url(""))
+    fn test_storage_key_is_namespaced() {
+        let prod = Client::builder().collection_name("cfr").build().unwrap();
+        let prod_path = Client::builder()
+            .server_url("https://firefox.settings.services.mozilla.com/v1/")
             .collection_name("cfr")
-            .storage(Box::new(MemoryStorage::new()))
-            .sync_if_empty(false)
             .build()
             .unwrap();
-
-        client
-            .storage
-            .store("main/cfr:collection", b"abc".to_vec())
+        let stage = Client::builder()
+            .server_url("https://settings.stage.mozaws.net/v1")
+            .collection_name("cfr")
+            .build()
+            .unwrap();
+        let other_root = Client::builder()
+            .collection_name("cfr")
+            .cert_root_hash("3C:01:44".to_string())
+            .build()
             .unwrap();
 
-        let err = client.get().unwrap_err();
-        assert_eq!(err.to_string(), "storage I/O error: cannot read from storage: cannot deserialize collection: expected value at line 1 column 1");
+        assert_eq!(prod._storage_key(), "cf4b44d781a639c1/main/cfr:collection");
+        assert_eq!(prod_path._storage_key(), prod._storage_key());
+        assert_ne!(stage._storage_key(), prod._storage_key());
+        assert_ne!(other_root._storage_key(), prod._storage_key());
     }
 
     #[test]
-    fn test_get_bad_stored_data_if_untrusted() {
+    fn test_legacy_storage_key_is_migrated() {
         init();
-        let mock_server = MockServer::start();
 
         let mut client = Client::builder()
-            .server_url(mock_server.url(""))
-            .collection_name("search-config")
+            .collection_name("cfr")
             .storage(Box::new(MemoryStorage::new()))
-            .verifier(Box::new(VerifierWithInvalidSignatureError {}))
             .sync_if_empty(false)
-            .trust_local(false)
             .build()
             .unwrap();
-
         let collection = Collection {
             bid: "main".to_owned(),
-            cid: "search-config".to_owned(),
+            cid: "cfr".to_owned(),
             metadata: json!({}),
-            records: vec![Record(json!({}))],
+            records: vec![Record(json!({"id": "a", "last_modified": 42}))],
             timestamp: 42,
             signer: "some-name".to_owned(),
         };
-        let collection_bytes: Vec<u8> = serde_json::to_string(&collection).unwrap().into();
         client
             .storage
-            .store("main/search-config:collection", collection_bytes)
+            .store(
+                "main/cfr:collection",
+                serde_json::to_vec(&collection).unwrap(),
+            )
             .unwrap();
 
-        let err = client.get().unwrap_err();
+        assert_eq!(client.get().unwrap(), collection.records);
         assert_eq!(
-            err.to_string(),
-            "content signature could not be verified: signature mismatch: fake invalid signature"
+            client.storage.keys(None).unwrap(),
+            vec![client._storage_key()]
         );
+
+        // Not for other servers.
+        let mut stage = Client::builder()
+            .server_url("https://settings.stage.mozaws.net/v1")
+            .collection_name("cfr")
+            .storage(Box::new(MemoryStorage::new()))
+            .sync_if_empty(false)
+            .build()
+            .unwrap();
+        stage
+            .storage
+            .store(
+                "main/cfr:collection",
+                serde_json::to_vec(&collection).unwrap(),
+            )
+            .unwrap();
+
+        assert!(stage.get().is_err());
     }
 
     #[test]
-    fn test_get_with_empty_records_list() {
+    fn test_get_resyncs_unsupported_schema() {
         init();
-
         let mock_server = MockServer::start();
+        let mut get_latest_change_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/monitor/collections/changes/changeset");
+            then.body(
+                r#"{
+                    "metadata": {},
+                    "changes": [{
+                        "id": "not-read",
+                        "last_modified": 42,
+                        "bucket": "main",
+                        "collection": "cfr"
+                    }],
+                    "timestamp": 42
+                }"#,
+            );
+        });
         let mut get_changeset_mock = mock_server.mock(|when, then| {
-            when.path("/buckets/main/collections/regions/changeset")
-                .query_param("_expected", "42");
+            when.path("/buckets/main/collections/cfr/changeset");
             then.body(
                 r#"{
                     "metadata": {},
-                    "changes": [],
-                    "timestamp": 0
+                    "changes": [{
+                        "id": "record-1",
+                        "last_modified": 42
+                    }],
+                    "timestamp": 42
                 }"#,
             );
         });
 
         let mut client = Client::builder()
             .server_url(mock_server.url(""))
-            .collection_name("regions")
+            .collection_name("cfr")
             .storage(Box::new(MemoryStorage::new()))
             .build()
             .unwrap();
+        // Stored by a newer version of the client (schema version 9).
+        let storage_key = client._storage_key();
+        client
+            .storage
+            .store(&storage_key, b"\x02\x01\x09{}".to_vec())
+            .unwrap();
 
-        client.sync(42).unwrap();
-
-        assert_eq!(client.get().unwrap().len(), 0);
+        let records = client.get().unwrap();
 
+        assert_eq!(records[0].id(), "record-1");
         get_changeset_mock.assert();
+        get_latest_change_mock.delete();
         get_changeset_mock.delete();
     }
 
+    #[cfg(feature = "encryption")]
     #[test]
-    fn test_get_return_previously_synced_records() {
+    fn test_get_does_not_resync_unreadable_data() {
+        use super::EncryptedStorage;
+        init();
+        let mock_server = MockServer::start();
+        let mut get_changeset_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/cfr/changeset");
+            then.body(r#"{"metadata": {}, "changes": [], "timestamp": 42}"#);
+        });
+
+        let key = Arc::new(Mutex::new([1; 32]));
+        let provided = key.clone();
+        let key_provider =
+            move || -> Result<[u8; 32], StorageError> { Ok(*provided.lock().unwrap()) };
+        let mut client = Client::builder()
+            .server_url(mock_server.url(""))
+            .collection_name("cfr")
+            .storage(Box::new(EncryptedStorage::new(
+                Box::new(MemoryStorage::new()),
+                Box::new(key_provider),
+            )))
+            .build()
+            .unwrap();
+        let collection = Collection {
+            bid: "main".to_owned(),
+            cid: "cfr".to_owned(),
+            metadata: json!({}),
+            records: vec![Record(json!({"id": "a", "last_modified": 42}))],
+            timestamp: 42,
+            signer: "some-name".to_owned(),
+        };
+        let storage_key = client._storage_key();
+        client
+            .store_collection(&storage_key, &collection, None)
+            .unwrap();
+
+        // Wrong key: the error is returned, and the stored data is not overwritten.
+        *key.lock().unwrap() = [2; 32];
+        assert!(matches!(
+            client.get(),
+            Err(ClientError::StorageError(StorageError::ReadError(_)))
+        ));
+
+        *key.lock().unwrap() = [1; 32];
+        assert_eq!(client.get().unwrap(), collection.records);
+        get_changeset_mock.assert_hits(0);
+        get_changeset_mock.delete();
+    }
+
+    #[test]
+    fn test_purge_and_clear_local() {
+        init();
+        let mock_server = MockServer::start();
+
+        let mut client = Client::builder()
+            .server_url(mock_server.url(""))
+            .collection_name("cfr")
+            .storage(Box::new(MemoryStorage::new()))
+            .sync_if_empty(false)
+            .build()
+            .unwrap();
+
+        let storage_key = client._storage_key();
+        client.storage.store(&storage_key, b"{}".to_vec()).unwrap();
+        client
+            .storage
+            .store("main/other:collection", b"{}".to_vec())
+            .unwrap();
+
+        client.purge().unwrap();
+        assert_eq!(
+            client.storage.keys(None).unwrap(),
+            vec!["main/other:collection"]
+        );
+        assert!(matches!(
+            client.get().unwrap_err(),
+            ClientError::StorageError(StorageError::KeyNotFound { .. })
+        ));
+
+        client.clear_local().unwrap();
+        assert!(client.storage.keys(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_get_bad_stored_data() {
         init();
+        let mock_server = MockServer::start();
+
+        let mut client = Client::builder()
+            .server_url(mock_server.url(""))
+            .collection_name("cfr")
+            .storage(Box::new(MemoryStorage::new()))
+            .sync_if_empty(false)
+            .build()
+            .unwrap();
+
+        let storage_key = client._storage_key();
+        client.storage.store(&storage_key, b"abc".to_vec()).unwrap();
+
+        let err = client.get().unwrap_err();
+        assert_eq!(err.to_string(), "storage I/O error: cannot read from storage: cannot deserialize collection: expected value at line 1 column 1");
+    }
 
+    #[test]
+    fn test_get_resyncs_unreadable_stored_data() {
+        init();
         let mock_server = MockServer::start();
+        let mut get_latest_change_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/monitor/collections/changes/changeset");
+            then.body(
+                r#"{
+                    "metadata": {},
+                    "changes": [{
+                        "id": "not-read",
+                        "last_modified": 42,
+                        "bucket": "main",
+                        "collection": "cfr"
+                    }],
+                    "timestamp": 42
+                }"#,
+            );
+        });
         let mut get_changeset_mock = mock_server.mock(|when, then| {
-            when.path("/buckets/main/collections/blocklist/changeset")
-                .query_param("_expected", "123");
+            when.path("/buckets/main/collections/cfr/changeset");
             then.body(
                 r#"{
                     "metadata": {},
                     "changes": [{
                         "id": "record-1",
-                        "last_modified": 123,
-                        "foo": "bar"
+                        "last_modified": 42
                     }],
-                    "timestamp": 123
+                    "timestamp": 42
                 }"#,
             );
         });
 
         let mut client = Client::builder()
             .server_url(mock_server.url(""))
-            .collection_name("blocklist")
+            .collection_name("cfr")
             .storage(Box::new(MemoryStorage::new()))
             .build()
             .unwrap();
 
-        client.sync(123).unwrap();
+        // Stored with a newer schema version.
+        let storage_key = client._storage_key();
+        client
+            .storage
+            .store(&storage_key, b"\x02\x01\x63{}".to_vec())
+            .unwrap();
 
         let records = client.get().unwrap();
+        assert_eq!(records[0].id(), "record-1");
 
-        assert_eq!(records.len(), 1);
-        assert_eq!(records[0]["foo"].as_str().unwrap(), "bar");
-
+        get_latest_change_mock.assert();
         get_changeset_mock.assert();
+        get_latest_change_mock.delete();
         get_changeset_mock.delete();
     }
 
     #[test]
-    fn test_sync_pulls_current_timestamp_from_changes_endpoint_if_none() {
+    fn test_get_resyncs_corrupted_stored_data() {
         init();
-
         let mock_server = MockServer::start();
         let mut get_latest_change_mock = mock_server.mock(|when, then| {
             when.path("/buckets/monitor/collections/changes/changeset");
@@ -782,271 +1623,1328 @@ mod tests {
                     "metadata": {},
                     "changes": [{
                         "id": "not-read",
-                        "last_modified": 123,
+                        "last_modified": 42,
                         "bucket": "main",
-                        "collection": "fxmonitor"
+                        "collection": "cfr"
                     }],
                     "timestamp": 42
                 }"#,
             );
         });
-
         let mut get_changeset_mock = mock_server.mock(|when, then| {
-            when.path("/buckets/main/collections/fxmonitor/changeset")
-                .query_param("_expected", "123");
+            when.path("/buckets/main/collections/cfr/changeset");
             then.body(
                 r#"{
-                        "metadata": {},
-                        "changes": [{
-                            "id": "record-1",
-                            "last_modified": 555,
-                            "foo": "bar"
-                        }],
-                        "timestamp": 555
-                    }"#,
+                    "metadata": {},
+                    "changes": [{
+                        "id": "record-1",
+                        "last_modified": 42
+                    }],
+                    "timestamp": 42
+                }"#,
+            );
+        });
+
+        let storages: Vec<Box<dyn Storage>> = vec![
+            Box::new(MemoryStorage::new()),
+            Box::new(TieredStorage::new(Box::new(MemoryStorage::new()))),
+            Box::new(RecordStorage::new(Box::new(MemoryStorage::new()))),
+        ];
+        for storage in storages {
+            let reports = Arc::new(Mutex::new(Vec::new()));
+            let received = reports.clone();
+            let mut client = Client::builder()
+                .server_url(mock_server.url(""))
+                .collection_name("cfr")
+                .storage(storage)
+                .corruption_handler(Box::new(move |key: &str, err: &StorageError| {
+                    received
+                        .lock()
+                        .unwrap()
+                        .push((key.to_string(), err.to_string()))
+                }))
+                .build()
+                .unwrap();
+
+            client.sync(None).unwrap();
+
+            // Flip a bit of the stored records (or of the index of records).
+            let storage_key = client._storage_key();
+            let mut stored = client.storage.retrieve(&storage_key).unwrap();
+            let last = stored.len() - 3;
+            stored[last] ^= 0x01;
+            client.storage.store(&storage_key, stored).unwrap();
+
+            let records = client.get().unwrap();
+            assert_eq!(records[0].id(), "record-1");
+            assert_eq!(
+                *reports.lock().unwrap(),
+                vec![(
+                    storage_key,
+                    "stored data is corrupted: checksum mismatch".to_string()
+                )]
+            );
+        }
+
+        get_latest_change_mock.assert_hits(6);
+        get_changeset_mock.assert_hits(6);
+        get_latest_change_mock.delete();
+        get_changeset_mock.delete();
+    }
+
+    struct VerifierWithExpiry {
+        calls: Arc<Mutex<u32>>,
+        expiry: u64,
+    }
+
+    impl Verification for VerifierWithExpiry {
+        fn verify_nist384p_chain(
+            &self,
+            _: u64,
+            _: &[u8],
+            _: &[u8],
+            _: &str,
+            _: &[u8],
+            _: &[u8],
+        ) -> Result<(), SignatureError> {
+            Ok(()) // unreachable.
+        }
+
+        fn verify_with_expiry(
+            &self,
+            _: &dyn HttpClient,
+            _: &Collection,
+            _: &str,
+        ) -> Result<Option<u64>, SignatureError> {
+            *self.calls.lock().unwrap() += 1;
+            Ok(Some(self.expiry))
+        }
+    }
+
+    #[test]
+    fn test_get_skips_verification_of_verified_content() {
+        init();
+        let day = 24 * 60 * 60;
+        let calls = Arc::new(Mutex::new(0));
+
+        let mut client = Client::builder()
+            .collection_name("cfr")
+            .storage(Box::new(MemoryStorage::new()))
+            .verifier(Box::new(VerifierWithExpiry {
+                calls: calls.clone(),
+                expiry: 10 * day,
+            }))
+            .sync_if_empty(false)
+            .trust_local(false)
+            .build()
+            .unwrap();
+
+        let mut collection = Collection {
+            bid: "main".to_owned(),
+            cid: "cfr".to_owned(),
+            metadata: json!({}),
+            records: vec![Record(json!({"id": "a", "last_modified": 42}))],
+            timestamp: 42,
+            signer: "some-name".to_owned(),
+        };
+        let storage_key = client._storage_key();
+        client
+            .storage
+            .store(&storage_key, serde_json::to_vec(&collection).unwrap())
+            .unwrap();
+
+        client.get().unwrap();
+        client.get().unwrap();
+        assert_eq!(*calls.lock().unwrap(), 1);
+
+        // Stored data changed.
+        collection.records[0] = Record(json!({"id": "a", "last_modified": 43}));
+        client
+            .storage
+            .store(&storage_key, serde_json::to_vec(&collection).unwrap())
+            .unwrap();
+        client.get().unwrap();
+        client.get().unwrap();
+        assert_eq!(*calls.lock().unwrap(), 2);
+
+        // Certificates are about to expire.
+        mock_instant::MockClock::advance(Duration::from_secs(9 * day + 1));
+        client.get().unwrap();
+        client.get().unwrap();
+        assert_eq!(*calls.lock().unwrap(), 4);
+        mock_instant::MockClock::set_time(Duration::default());
+    }
+
+    #[test]
+    fn test_get_skips_verification_persisted_with_key() {
+        init();
+        let day = 24 * 60 * 60;
+        let calls = Arc::new(Mutex::new(0));
+        let new_client = |storage: Box<dyn Storage>, key: [u8; 32]| {
+            Client::builder()
+                .collection_name("cfr")
+                .storage(storage)
+                .verifier(Box::new(VerifierWithExpiry {
+                    calls: calls.clone(),
+                    expiry: 10 * day,
+                }))
+                .verified_content_key(VerifiedContentKey(key))
+                .sync_if_empty(false)
+                .trust_local(false)
+                .build()
+                .unwrap()
+        };
+
+        let mut client = new_client(Box::new(MemoryStorage::new()), [1; 32]);
+        let collection = Collection {
+            bid: "main".to_owned(),
+            cid: "cfr".to_owned(),
+            metadata: json!({}),
+            records: vec![Record(json!({"id": "a", "last_modified": 42}))],
+            timestamp: 42,
+            signer: "some-name".to_owned(),
+        };
+        let storage_key = client._storage_key();
+        client
+            .store_collection(&storage_key, &collection, None)
+            .unwrap();
+        client.get().unwrap();
+        assert_eq!(*calls.lock().unwrap(), 1);
+
+        // Verified by a previous client with the same key.
+        let mut client = new_client(client.storage, [1; 32]);
+        client.get().unwrap();
+        assert_eq!(*calls.lock().unwrap(), 1);
+
+        // Not with another key.
+        let mut client = new_client(client.storage, [2; 32]);
+        client.get().unwrap();
+        assert_eq!(*calls.lock().unwrap(), 2);
+
+        // Nor if the persisted verification was tampered.
+        let verification_key = client._verification_storage_key();
+        let mut stored = client.storage.retrieve(&verification_key).unwrap();
+        stored[0] ^= 0x01;
+        client.storage.store(&verification_key, stored).unwrap();
+        let mut client = new_client(client.storage, [2; 32]);
+        client.get().unwrap();
+        assert_eq!(*calls.lock().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_get_verifies_again_if_persisted_verification_is_evicted() {
+        init();
+        let calls = Arc::new(Mutex::new(0));
+        let new_client = |storage: Box<dyn Storage>| {
+            Client::builder()
+                .collection_name("cfr")
+                .storage(storage)
+                .verifier(Box::new(VerifierWithExpiry {
+                    calls: calls.clone(),
+                    expiry: 10 * 24 * 60 * 60,
+                }))
+                .verified_content_key(VerifiedContentKey([1; 32]))
+                .sync_if_empty(false)
+                .trust_local(false)
+                .build()
+                .unwrap()
+        };
+
+        let mut client = new_client(Box::new(MemoryStorage::new()));
+        let collection = Collection {
+            bid: "main".to_owned(),
+            cid: "cfr".to_owned(),
+            metadata: json!({}),
+            records: vec![Record(json!({"id": "a", "last_modified": 42}))],
+            timestamp: 42,
+            signer: "some-name".to_owned(),
+        };
+        let storage_key = client._storage_key();
+        client
+            .store_collection(&storage_key, &collection, None)
+            .unwrap();
+        client.get().unwrap();
+        assert_eq!(*calls.lock().unwrap(), 1);
+
+        // Only the verification entry was evicted (eg. by a bounded storage).
+        let verification_key = client._verification_storage_key();
+        client.storage.delete(&verification_key).unwrap();
+        let mut client = new_client(client.storage);
+
+        assert_eq!(client.get().unwrap(), collection.records);
+        assert_eq!(*calls.lock().unwrap(), 2);
+        assert!(client.storage.retrieve(&verification_key).is_ok());
+    }
+
+    #[test]
+    fn test_get_bad_stored_data_if_untrusted() {
+        init();
+        let mock_server = MockServer::start();
+
+        let mut client = Client::builder()
+            .server_url(mock_server.url(""))
+            .collection_name("search-config")
+            .storage(Box::new(MemoryStorage::new()))
+            .verifier(Box::new(VerifierWithInvalidSignatureError {}))
+            .sync_if_empty(false)
+            .trust_local(false)
+            .build()
+            .unwrap();
+
+        let collection = Collection {
+            bid: "main".to_owned(),
+            cid: "search-config".to_owned(),
+            metadata: json!({}),
+            records: vec![Record(json!({}))],
+            timestamp: 42,
+            signer: "some-name".to_owned(),
+        };
+        let collection_bytes: Vec<u8> = serde_json::to_string(&collection).unwrap().into();
+        let storage_key = client._storage_key();
+        client
+            .storage
+            .store(&storage_key, collection_bytes)
+            .unwrap();
+
+        let err = client.get().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "content signature could not be verified: signature mismatch: fake invalid signature"
+        );
+    }
+
+    #[test]
+    fn test_get_with_empty_records_list() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mut get_changeset_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/regions/changeset")
+                .query_param("_expected", "42");
+            then.body(
+                r#"{
+                    "metadata": {},
+                    "changes": [],
+                    "timestamp": 0
+                }"#,
+            );
+        });
+
+        let mut client = Client::builder()
+            .server_url(mock_server.url(""))
+            .collection_name("regions")
+            .storage(Box::new(MemoryStorage::new()))
+            .build()
+            .unwrap();
+
+        client.sync(42).unwrap();
+
+        assert_eq!(client.get().unwrap().len(), 0);
+
+        get_changeset_mock.assert();
+        get_changeset_mock.delete();
+    }
+
+    #[test]
+    fn test_get_return_previously_synced_records() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mut get_changeset_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/blocklist/changeset")
+                .query_param("_expected", "123");
+            then.body(
+                r#"{
+                    "metadata": {},
+                    "changes": [{
+                        "id": "record-1",
+                        "last_modified": 123,
+                        "foo": "bar"
+                    }],
+                    "timestamp": 123
+                }"#,
+            );
+        });
+
+        let mut client = Client::builder()
+            .server_url(mock_server.url(""))
+            .collection_name("blocklist")
+            .storage(Box::new(MemoryStorage::new()))
+            .build()
+            .unwrap();
+
+        client.sync(123).unwrap();
+
+        let records = client.get().unwrap();
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["foo"].as_str().unwrap(), "bar");
+
+        get_changeset_mock.assert();
+        get_changeset_mock.delete();
+    }
+
+    #[test]
+    fn test_sync_pulls_current_timestamp_from_changes_endpoint_if_none() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mut get_latest_change_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/monitor/collections/changes/changeset");
+            then.body(
+                r#"{
+                    "metadata": {},
+                    "changes": [{
+                        "id": "not-read",
+                        "last_modified": 123,
+                        "bucket": "main",
+                        "collection": "fxmonitor"
+                    }],
+                    "timestamp": 42
+                }"#,
+            );
+        });
+
+        let mut get_changeset_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/fxmonitor/changeset")
+                .query_param("_expected", "123");
+            then.body(
+                r#"{
+                        "metadata": {},
+                        "changes": [{
+                            "id": "record-1",
+                            "last_modified": 555,
+                            "foo": "bar"
+                        }],
+                        "timestamp": 555
+                    }"#,
+            );
+        });
+
+        let mut client = Client::builder()
+            .server_url(mock_server.url(""))
+            .collection_name("fxmonitor")
+            .build()
+            .unwrap();
+
+        client.sync(None).unwrap();
+
+        get_changeset_mock.assert();
+        get_latest_change_mock.assert();
+        get_changeset_mock.delete();
+        get_latest_change_mock.delete();
+    }
+
+    #[test]
+    fn test_sync_uses_specified_expected_parameter() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mut get_changeset_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/pioneers/changeset")
+                .query_param("_expected", "13");
+            then.body(
+                r#"{
+                    "metadata": {},
+                    "changes": [{
+                        "id": "record-1",
+                        "last_modified": 13,
+                        "foo": "bar"
+                    }],
+                    "timestamp": 13
+                }"#,
+            );
+        });
+
+        let mut client = Client::builder()
+            .server_url(mock_server.url(""))
+            .collection_name("pioneers")
+            .build()
+            .unwrap();
+
+        client.sync(13).unwrap();
+
+        get_changeset_mock.assert();
+        get_changeset_mock.delete();
+    }
+
+    #[test]
+    fn test_sync_fails_with_unknown_collection() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mut get_latest_change_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/monitor/collections/changes/changeset");
+            then.body(
+                r#"{
+                    "metadata": {},
+                    "changes": [{
+                        "id": "not-read",
+                        "last_modified": 123,
+                        "bucket": "main",
+                        "collection": "fxmonitor"
+                    }],
+                    "timestamp": 42
+                }"#,
+            );
+        });
+
+        let mut client = Client::builder()
+            .server_url(mock_server.url(""))
+            .collection_name("url-classifier-skip-urls")
+            .build()
+            .unwrap();
+
+        let err = client.sync(None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "API failure: unknown collection: main/url-classifier-skip-urls",
+        );
+
+        get_latest_change_mock.assert();
+        get_latest_change_mock.delete();
+    }
+
+    #[test]
+    #[cfg(feature = "ring_verifier")]
+    fn test_sync_uses_x5u_from_metadata_to_verify_signatures() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mut get_changeset_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/onecrl/changeset")
+                .query_param("_expected", "42");
+            then.body(
+                r#"{
+                    "metadata": {
+                        "missing": "x5u"
+                    },
+                    "changes": [{
+                        "id": "record-1",
+                        "last_modified": 13,
+                        "foo": "bar"
+                    }],
+                    "timestamp": 13
+                }"#,
+            );
+        });
+
+        let mut client = Client::builder()
+            .server_url(mock_server.url(""))
+            .collection_name("onecrl")
+            .verifier(Box::new(RingVerifier {}))
+            .build()
+            .unwrap();
+
+        let err = client.sync(42).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "content signature could not be verified: signature payload has no x5u field"
+        );
+
+        get_changeset_mock.assert();
+        get_changeset_mock.delete();
+    }
+    #[test]
+    fn test_sync_wraps_signature_errors() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mut get_changeset_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/password-recipes/changeset")
+                .query_param("_expected", "42");
+            then.body(
+                r#"{
+                    "metadata": {},
+                    "changes": [{
+                        "id": "record-1",
+                        "last_modified": 13,
+                        "foo": "bar"
+                    }],
+                    "timestamp": 13
+                }"#,
+            );
+        });
+
+        let mut client = Client::builder()
+            .server_url(mock_server.url(""))
+            .collection_name("password-recipes")
+            .verifier(Box::new(VerifierWithInvalidSignatureError {}))
+            .build()
+            .unwrap();
+
+        let err = client.sync(42).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "content signature could not be verified: signature mismatch: fake invalid signature"
+        );
+
+        get_changeset_mock.assert();
+        get_changeset_mock.delete();
+    }
+
+    #[test]
+    fn test_sync_returns_collection_with_merged_changes() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mut get_changeset_mock_1 = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/onecrl/changeset")
+                .query_param("_expected", "15");
+            then.body(
+                r#"{
+                    "metadata": {},
+                    "changes": [{
+                        "id": "record-1",
+                        "last_modified": 15
+                    }, {
+                        "id": "record-2",
+                        "last_modified": 14,
+                        "field": "before"
+                    }, {
+                        "id": "record-3",
+                        "last_modified": 13
+                    }],
+                    "timestamp": 15
+                }"#,
+            );
+        });
+
+        let mut client = Client::builder()
+            .server_url(mock_server.url(""))
+            .collection_name("onecrl")
+            .storage(Box::new(MemoryStorage::new()))
+            .build()
+            .unwrap();
+
+        let res = client.sync(15).unwrap();
+        assert_eq!(res.records.len(), 3);
+
+        get_changeset_mock_1.assert();
+        get_changeset_mock_1.delete();
+
+        let mut get_changeset_mock_2 = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/onecrl/changeset")
+                .query_param("_since", "15")
+                .query_param("_expected", "42");
+            then.body(
+                r#"{
+                    "metadata": {},
+                    "changes": [{
+                        "id": "record-1",
+                        "last_modified": 42,
+                        "field": "after"
+                    }, {
+                        "id": "record-4",
+                        "last_modified": 30
+                    }, {
+                        "id": "record-2",
+                        "last_modified": 20,
+                        "delete": true
+                    }],
+                    "timestamp": 42
+                }"#,
+            );
+        });
+
+        let res = client.sync(42).unwrap();
+        assert_eq!(res.records.len(), 4);
+
+        let record_1_idx = res
+            .records
+            .iter()
+            .position(|r| r.id() == "record-1")
+            .unwrap();
+        let record_1 = &res.records[record_1_idx];
+        assert_eq!(record_1["field"].as_str().unwrap(), "after");
+
+        get_changeset_mock_2.assert();
+        get_changeset_mock_2.delete();
+    }
+
+    #[cfg(feature = "sqlite_storage")]
+    #[test]
+    fn test_sync_applies_changes_to_sqlite_storage() {
+        use super::SqliteStorage;
+
+        init();
+
+        let mock_server = MockServer::start();
+        let mut get_changeset_mock_1 = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/onecrl/changeset")
+                .query_param("_expected", "15");
+            then.body(
+                r#"{
+                    "metadata": {},
+                    "changes": [{
+                        "id": "record-1",
+                        "last_modified": 15
+                    }, {
+                        "id": "record-2",
+                        "last_modified": 14
+                    }],
+                    "timestamp": 15
+                }"#,
+            );
+        });
+        let mut get_changeset_mock_2 = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/onecrl/changeset")
+                .query_param("_since", "15")
+                .query_param("_expected", "42");
+            then.body(
+                r#"{
+                    "metadata": {},
+                    "changes": [{
+                        "id": "record-3",
+                        "last_modified": 42
+                    }, {
+                        "id": "record-1",
+                        "last_modified": 20,
+                        "deleted": true
+                    }],
+                    "timestamp": 42
+                }"#,
+            );
+        });
+
+        let mut client = Client::builder()
+            .server_url(mock_server.url(""))
+            .collection_name("onecrl")
+            .storage(Box::new(SqliteStorage::open_in_memory().unwrap()))
+            .build()
+            .unwrap();
+
+        client.sync(15).unwrap();
+        client.sync(42).unwrap();
+
+        let mut ids: Vec<String> = client
+            .get()
+            .unwrap()
+            .iter()
+            .map(|r| r.id().to_string())
+            .collect();
+        ids.sort();
+        assert_eq!(ids, vec!["record-2", "record-3"]);
+
+        get_changeset_mock_1.assert();
+        get_changeset_mock_2.assert();
+        get_changeset_mock_1.delete();
+        get_changeset_mock_2.delete();
+    }
+
+    struct VerifierRejectingTimestamp {
+        rejected: Arc<Mutex<Option<u64>>>,
+    }
+
+    impl Verification for VerifierRejectingTimestamp {
+        fn verify_nist384p_chain(
+            &self,
+            _: u64,
+            _: &[u8],
+            _: &[u8],
+            _: &str,
+            _: &[u8],
+            _: &[u8],
+        ) -> Result<(), SignatureError> {
+            Ok(()) // unreachable.
+        }
+
+        fn verify(
+            &self,
+            _: &dyn HttpClient,
+            collection: &Collection,
+            _: &str,
+        ) -> Result<(), SignatureError> {
+            if *self.rejected.lock().unwrap() == Some(collection.timestamp) {
+                return Err(SignatureError::MismatchError("fake".to_owned()));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_sync_sends_changeset_validators_of_stored_data() {
+        init();
+
+        let mock_server = MockServer::start();
+        let rejected = Arc::new(Mutex::new(None));
+        let mut client = Client::builder()
+            .server_url(mock_server.url(""))
+            .collection_name("regions")
+            .storage(Box::new(MemoryStorage::new()))
+            .verifier(Box::new(VerifierRejectingTimestamp {
+                rejected: rejected.clone(),
+            }))
+            .build()
+            .unwrap();
+
+        // The changeset served is behind the expected timestamp (eg. lagging CDN).
+        let mut lagging_changeset_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/regions/changeset");
+            then.header("ETag", "\"41\"").body(
+                r#"{
+                    "metadata": {},
+                    "changes": [{
+                        "id": "record-1",
+                        "last_modified": 41
+                    }],
+                    "timestamp": 41
+                }"#,
+            );
+        });
+        assert_eq!(client.sync(42).unwrap().timestamp, 41);
+        lagging_changeset_mock.assert();
+        lagging_changeset_mock.delete();
+
+        let mut not_modified_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/regions/changeset")
+                .query_param("_since", "41")
+                .header("If-None-Match", "\"41\"");
+            then.status(304);
+        });
+        let mut get_changes_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/regions/changeset")
+                .query_param("_since", "41");
+            then.status(500);
+        });
+        let mut get_changeset_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/regions/changeset")
+                .matches(|req| {
+                    let params = req.query_params.as_deref().unwrap_or_default();
+                    !params.iter().any(|(name, _)| name == "_since")
+                });
+            then.header("ETag", "\"42\"").body(
+                r#"{
+                    "metadata": {},
+                    "changes": [{
+                        "id": "record-1",
+                        "last_modified": 41
+                    }, {
+                        "id": "record-2",
+                        "last_modified": 42
+                    }],
+                    "timestamp": 42
+                }"#,
+            );
+        });
+
+        // Not modified, and local data is valid.
+        let res = client.sync(42).unwrap();
+        assert_eq!(res.timestamp, 41);
+        not_modified_mock.assert_hits(1);
+        get_changeset_mock.assert_hits(0);
+
+        // Not modified, but local data is invalid: fetch everything again without validators.
+        *rejected.lock().unwrap() = Some(41);
+        let res = client.sync(42).unwrap();
+        assert_eq!(res.timestamp, 42);
+        assert_eq!(res.records.len(), 2);
+        not_modified_mock.assert_hits(2);
+        get_changes_mock.assert_hits(0);
+        get_changeset_mock.assert_hits(1);
+
+        not_modified_mock.delete();
+        get_changes_mock.delete();
+        get_changeset_mock.delete();
+    }
+
+    #[test]
+    fn test_sync_enters_backoff_on_not_modified_changeset() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mut client = Client::builder()
+            .server_url(mock_server.url(""))
+            .collection_name("regions")
+            .storage(Box::new(MemoryStorage::new()))
+            .verifier(Box::new(DummyVerifier {}))
+            .build()
+            .unwrap();
+
+        let mut not_modified_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/regions/changeset")
+                .header("If-None-Match", "\"41\"");
+            then.status(304).header("Backoff", "300");
+        });
+        let mut get_changeset_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/regions/changeset");
+            then.header("ETag", "\"41\"").body(
+                r#"{
+                    "metadata": {},
+                    "changes": [{
+                        "id": "record-1",
+                        "last_modified": 41
+                    }],
+                    "timestamp": 41
+                }"#,
+            );
+        });
+
+        // The changeset served is behind the expected timestamp, and is requested again.
+        assert_eq!(client.sync(42).unwrap().timestamp, 41);
+        assert_eq!(client.sync(42).unwrap().timestamp, 41);
+        let err = client.sync(42).unwrap_err();
+
+        assert!(matches!(err, ClientError::BackoffError(_)));
+        get_changeset_mock.assert_hits(1);
+        not_modified_mock.assert_hits(1);
+
+        not_modified_mock.delete();
+        get_changeset_mock.delete();
+    }
+
+    #[test]
+    fn test_sync_skips_download_if_not_modified() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mut not_modified_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/monitor/collections/changes/changeset")
+                .header("If-None-Match", "\"123\"");
+            then.status(304);
+        });
+        let mut get_latest_change_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/monitor/collections/changes/changeset");
+            then.header("ETag", "\"123\"").body(
+                r#"{
+                    "metadata": {},
+                    "changes": [{
+                        "id": "not-read",
+                        "last_modified": 123,
+                        "bucket": "main",
+                        "collection": "regions"
+                    }],
+                    "timestamp": 123
+                }"#,
+            );
+        });
+        let mut get_changeset_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/regions/changeset")
+                .query_param("_expected", "123");
+            then.body(
+                r#"{
+                    "metadata": {},
+                    "changes": [{
+                        "id": "record-1",
+                        "last_modified": 123
+                    }],
+                    "timestamp": 123
+                }"#,
             );
         });
 
         let mut client = Client::builder()
             .server_url(mock_server.url(""))
-            .collection_name("fxmonitor")
+            .collection_name("regions")
+            .storage(Box::new(MemoryStorage::new()))
             .build()
             .unwrap();
 
         client.sync(None).unwrap();
+        let res = client.sync(None).unwrap();
+
+        assert_eq!(res.timestamp, 123);
+        assert_eq!(res.records.len(), 1);
+        get_latest_change_mock.assert_hits(1);
+        not_modified_mock.assert_hits(1);
+        get_changeset_mock.assert_hits(1);
 
-        get_changeset_mock.assert();
-        get_latest_change_mock.assert();
-        get_changeset_mock.delete();
         get_latest_change_mock.delete();
+        not_modified_mock.delete();
+        get_changeset_mock.delete();
     }
 
     #[test]
-    fn test_sync_uses_specified_expected_parameter() {
+    fn test_server_info_is_cached() {
         init();
 
         let mock_server = MockServer::start();
-        let mut get_changeset_mock = mock_server.mock(|when, then| {
-            when.path("/buckets/main/collections/pioneers/changeset")
-                .query_param("_expected", "13");
+        let mut server_info_mock = mock_server.mock(|when, then| {
+            when.path("/");
             then.body(
                 r#"{
-                    "metadata": {},
-                    "changes": [{
-                        "id": "record-1",
-                        "last_modified": 13,
-                        "foo": "bar"
-                    }],
-                    "timestamp": 13
+                    "project_name": "Remote Settings PROD",
+                    "project_version": "29.1.0",
+                    "http_api_version": "1.22",
+                    "url": "https://firefox.settings.services.mozilla.com/v1/",
+                    "eos": "2042-01-01",
+                    "capabilities": {
+                        "attachments": {
+                            "base_url": "https://cdn/"
+                        }
+                    }
                 }"#,
             );
         });
 
         let mut client = Client::builder()
             .server_url(mock_server.url(""))
-            .collection_name("pioneers")
+            .collection_name("cfr")
             .build()
             .unwrap();
 
-        client.sync(13).unwrap();
+        assert_eq!(client.server_info().unwrap().project_version, "29.1.0");
+        let info = client.server_info().unwrap();
+        assert_eq!(info.eos.as_deref(), Some("2042-01-01"));
+        assert_eq!(info.attachments_base_url(), Some("https://cdn/"));
 
-        get_changeset_mock.assert();
-        get_changeset_mock.delete();
+        server_info_mock.assert_hits(1);
+        server_info_mock.delete();
     }
 
     #[test]
-    fn test_sync_fails_with_unknown_collection() {
+    fn test_sync_calls_alert_handler_on_not_modified_changes() {
         init();
 
         let mock_server = MockServer::start();
+        let alert = r#"{"code": "hard-eol", "message": "Service was decommissioned"}"#;
+        let mut not_modified_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/monitor/collections/changes/changeset")
+                .header("If-None-Match", "\"13\"");
+            then.status(304).header("Alert", alert);
+        });
         let mut get_latest_change_mock = mock_server.mock(|when, then| {
             when.path("/buckets/monitor/collections/changes/changeset");
-            then.body(
+            then.header("ETag", "\"13\"").header("Alert", alert).body(
                 r#"{
                     "metadata": {},
                     "changes": [{
                         "id": "not-read",
-                        "last_modified": 123,
+                        "last_modified": 13,
                         "bucket": "main",
-                        "collection": "fxmonitor"
+                        "collection": "pioneers"
                     }],
-                    "timestamp": 42
+                    "timestamp": 13
                 }"#,
             );
         });
+        let mut get_changeset_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/pioneers/changeset");
+            then.body(r#"{"metadata": {}, "changes": [], "timestamp": 13}"#);
+        });
 
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let received = alerts.clone();
         let mut client = Client::builder()
             .server_url(mock_server.url(""))
-            .collection_name("url-classifier-skip-urls")
+            .collection_name("pioneers")
+            .storage(Box::new(MemoryStorage::new()))
+            .alert_handler(Box::new(move |alert: &Alert| {
+                received.lock().unwrap().push(alert.clone())
+            }))
             .build()
             .unwrap();
 
-        let err = client.sync(None).unwrap_err();
-        assert_eq!(
-            err.to_string(),
-            "API failure: unknown collection: main/url-classifier-skip-urls",
-        );
+        client.sync(None).unwrap();
+        client.sync(None).unwrap();
 
-        get_latest_change_mock.assert();
+        let alerts = alerts.lock().unwrap();
+        assert_eq!(alerts.len(), 2);
+        assert!(alerts.iter().all(|alert| alert.code == "hard-eol"));
+
+        get_latest_change_mock.assert_hits(1);
+        not_modified_mock.assert_hits(1);
+        get_changeset_mock.assert_hits(1);
         get_latest_change_mock.delete();
+        not_modified_mock.delete();
+        get_changeset_mock.delete();
     }
 
     #[test]
-    #[cfg(feature = "ring_verifier")]
-    fn test_sync_uses_x5u_from_metadata_to_verify_signatures() {
+    fn test_sync_calls_alert_handler() {
         init();
 
         let mock_server = MockServer::start();
         let mut get_changeset_mock = mock_server.mock(|when, then| {
-            when.path("/buckets/main/collections/onecrl/changeset")
-                .query_param("_expected", "42");
-            then.body(
-                r#"{
-                    "metadata": {
-                        "missing": "x5u"
-                    },
-                    "changes": [{
-                        "id": "record-1",
-                        "last_modified": 13,
-                        "foo": "bar"
-                    }],
-                    "timestamp": 13
-                }"#,
-            );
+            when.path("/buckets/main/collections/pioneers/changeset");
+            then.header(
+                "Alert",
+                r#"{"code": "soft-eol", "message": "Service is ending", "url": "https://eol"}"#,
+            )
+            .body(r#"{"metadata": {}, "changes": [], "timestamp": 13}"#);
         });
 
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let received = alerts.clone();
         let mut client = Client::builder()
             .server_url(mock_server.url(""))
-            .collection_name("onecrl")
-            .verifier(Box::new(RingVerifier {}))
+            .collection_name("pioneers")
+            .alert_handler(Box::new(move |alert: &Alert| {
+                received.lock().unwrap().push(alert.clone())
+            }))
             .build()
             .unwrap();
 
-        let err = client.sync(42).unwrap_err();
+        client.sync(13).unwrap();
 
-        assert_eq!(
-            err.to_string(),
-            "content signature could not be verified: signature payload has no x5u field"
-        );
+        let alerts = alerts.lock().unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].code, "soft-eol");
+        assert_eq!(alerts[0].url.as_deref(), Some("https://eol"));
 
         get_changeset_mock.assert();
         get_changeset_mock.delete();
     }
+
     #[test]
-    fn test_sync_wraps_signature_errors() {
+    fn test_sync_uses_specified_http_client() {
+        let mut client = Client::builder()
+            .server_url("https://example.com/v1")
+            .collection_name("cfr")
+            .http_client(Box::new(FakeHttpClient {}))
+            .build()
+            .unwrap();
+
+        let res = client.sync(42).unwrap();
+
+        assert_eq!(res.timestamp, 42);
+        assert_eq!(res.records.len(), 1);
+        assert_eq!(res.records[0].id(), "record-1");
+    }
+
+    #[test]
+    fn test_sync_sends_user_agent_and_headers() {
         init();
 
         let mock_server = MockServer::start();
-        let mut get_changeset_mock = mock_server.mock(|when, then| {
-            when.path("/buckets/main/collections/password-recipes/changeset")
-                .query_param("_expected", "42");
+        let mut get_latest_change_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/monitor/collections/changes/changeset")
+                .header("User-Agent", "firefox/94.0")
+                .header("X-Automation", "1");
             then.body(
                 r#"{
                     "metadata": {},
                     "changes": [{
-                        "id": "record-1",
+                        "id": "not-read",
                         "last_modified": 13,
-                        "foo": "bar"
+                        "bucket": "main",
+                        "collection": "pioneers"
                     }],
                     "timestamp": 13
                 }"#,
             );
         });
+        let mut get_changeset_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/pioneers/changeset")
+                .header("User-Agent", "firefox/94.0")
+                .header("X-Automation", "1");
+            then.body(r#"{"metadata": {}, "changes": [], "timestamp": 13}"#);
+        });
 
         let mut client = Client::builder()
             .server_url(mock_server.url(""))
-            .collection_name("password-recipes")
-            .verifier(Box::new(VerifierWithInvalidSignatureError {}))
+            .collection_name("pioneers")
+            .user_agent("firefox/94.0")
+            .header("X-Automation", "1")
             .build()
             .unwrap();
 
-        let err = client.sync(42).unwrap_err();
+        client.sync(None).unwrap();
+
+        get_latest_change_mock.assert();
+        get_changeset_mock.assert();
+        get_latest_change_mock.delete();
+        get_changeset_mock.delete();
+    }
+
+    #[test]
+    fn test_timeouts_require_supporting_http_client() {
+        let err = Client::builder()
+            .collection_name("cfr")
+            .read_timeout(Duration::from_secs(10))
+            .build()
+            .unwrap_err();
+
         assert_eq!(
             err.to_string(),
-            "content signature could not be verified: signature mismatch: fake invalid signature"
+            "timeouts are not supported by the HTTP client"
         );
+    }
 
-        get_changeset_mock.assert();
-        get_changeset_mock.delete();
+    #[test]
+    fn test_sync_passes_timeouts_to_http_client() {
+        struct TimeoutHttpClient {}
+
+        impl HttpClient for TimeoutHttpClient {
+            fn send(&self, _: Request) -> Result<Response, viaduct::Error> {
+                panic!("Timeouts were not passed");
+            }
+
+            fn send_with_timeouts(
+                &self,
+                request: Request,
+                connect_timeout: Option<Duration>,
+                read_timeout: Option<Duration>,
+            ) -> Result<Response, viaduct::Error> {
+                assert_eq!(connect_timeout, Some(Duration::from_secs(3)));
+                assert_eq!(read_timeout, Some(Duration::from_secs(10)));
+                FakeHttpClient {}.send(request)
+            }
+
+            fn supports_timeouts(&self) -> bool {
+                true
+            }
+        }
+
+        let mut client = Client::builder()
+            .server_url("https://example.com/v1")
+            .collection_name("cfr")
+            .http_client(Box::new(TimeoutHttpClient {}))
+            .connect_timeout(Duration::from_secs(3))
+            .read_timeout(Duration::from_secs(10))
+            .build()
+            .unwrap();
+
+        assert_eq!(client.sync(42).unwrap().timestamp, 42);
     }
 
     #[test]
-    fn test_sync_returns_collection_with_merged_changes() {
+    fn test_sync_reports_metrics() {
         init();
 
+        struct RecordingMetrics {
+            events: Arc<Mutex<Vec<String>>>,
+        }
+
+        impl Metrics for RecordingMetrics {
+            fn sync_started(&self, bid: &str, cid: &str) {
+                self.push(format!("started {}/{}", bid, cid));
+            }
+
+            fn sync_finished(
+                &self,
+                _: &str,
+                _: &str,
+                _: Duration,
+                result: Result<(), &ClientError>,
+            ) {
+                self.push(format!("finished ok={}", result.is_ok()));
+            }
+
+            fn changeset_downloaded(&self, _: &str, _: &str, size: Option<u64>) {
+                self.push(format!("downloaded {}", size.is_some()));
+            }
+
+            fn changes_applied(&self, _: &str, _: &str, count: usize) {
+                self.push(format!("applied {}", count));
+            }
+
+            fn verification_finished(
+                &self,
+                _: &str,
+                _: &str,
+                _: Duration,
+                result: Result<(), &SignatureError>,
+            ) {
+                self.push(format!("verified ok={}", result.is_ok()));
+            }
+
+            fn backoff_entered(&self, _: &str, _: &str, duration: Duration) {
+                self.push(format!("backoff {}", duration.as_secs()));
+            }
+        }
+
+        impl RecordingMetrics {
+            fn push(&self, event: String) {
+                self.events.lock().unwrap().push(event);
+            }
+        }
+
         let mock_server = MockServer::start();
-        let mut get_changeset_mock_1 = mock_server.mock(|when, then| {
-            when.path("/buckets/main/collections/onecrl/changeset")
-                .query_param("_expected", "15");
-            then.body(
+        let mut get_changeset_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/pioneers/changeset");
+            then.header("Backoff", "30").body(
                 r#"{
                     "metadata": {},
                     "changes": [{
                         "id": "record-1",
-                        "last_modified": 15
+                        "last_modified": 13
                     }, {
                         "id": "record-2",
-                        "last_modified": 14,
-                        "field": "before"
-                    }, {
-                        "id": "record-3",
-                        "last_modified": 13
+                        "last_modified": 12
                     }],
-                    "timestamp": 15
+                    "timestamp": 13
                 }"#,
             );
         });
 
+        let events = Arc::new(Mutex::new(Vec::new()));
         let mut client = Client::builder()
             .server_url(mock_server.url(""))
-            .collection_name("onecrl")
-            .storage(Box::new(MemoryStorage::new()))
+            .collection_name("pioneers")
+            .metrics(Box::new(RecordingMetrics {
+                events: events.clone(),
+            }))
             .build()
             .unwrap();
 
-        let res = client.sync(15).unwrap();
-        assert_eq!(res.records.len(), 3);
+        client.sync(13).unwrap();
+        client.sync(13).unwrap_err(); // Backoff.
 
-        get_changeset_mock_1.assert();
-        get_changeset_mock_1.delete();
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "started main/pioneers",
+                "downloaded true",
+                "backoff 30",
+                "applied 2",
+                "verified ok=true",
+                "finished ok=true",
+                "started main/pioneers",
+                "finished ok=false",
+            ]
+        );
 
-        let mut get_changeset_mock_2 = mock_server.mock(|when, then| {
-            when.path("/buckets/main/collections/onecrl/changeset")
-                .query_param("_since", "15")
-                .query_param("_expected", "42");
+        get_changeset_mock.assert();
+        get_changeset_mock.delete();
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_sync_stores_collection_with_codec() {
+        use super::CborCodec;
+        init();
+
+        let mock_server = MockServer::start();
+        let mut get_changeset_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/regions/changeset");
             then.body(
                 r#"{
                     "metadata": {},
                     "changes": [{
                         "id": "record-1",
-                        "last_modified": 42,
-                        "field": "after"
-                    }, {
-                        "id": "record-4",
-                        "last_modified": 30
-                    }, {
-                        "id": "record-2",
-                        "last_modified": 20,
-                        "delete": true
+                        "last_modified": 13
                     }],
-                    "timestamp": 42
+                    "timestamp": 13
                 }"#,
             );
         });
 
-        let res = client.sync(42).unwrap();
-        assert_eq!(res.records.len(), 4);
-
-        let record_1_idx = res
-            .records
-            .iter()
-            .position(|r| r.id() == "record-1")
-            .unwrap();
-        let record_1 = &res.records[record_1_idx];
-        assert_eq!(record_1["field"].as_str().unwrap(), "after");
+        let storages: Vec<Box<dyn Storage>> = vec![
+            Box::new(MemoryStorage::new()),
+            // Writes through to its backend with the codec of the client.
+            Box::new(TieredStorage::new(Box::new(MemoryStorage::new()))),
+        ];
+        for storage in storages {
+            let mut client = Client::builder()
+                .server_url(mock_server.url(""))
+                .collection_name("regions")
+                .storage(storage)
+                .codec(Box::new(CborCodec {}))
+                .sync_if_empty(false)
+                .build()
+                .unwrap();
+
+            client.sync(13).unwrap();
+
+            let stored = client.storage.retrieve(&client._storage_key()).unwrap();
+            assert_eq!(&stored[..3], b"\x03\x02\x01");
+            assert_eq!(client.get().unwrap()[0].id(), "record-1");
+        }
 
-        get_changeset_mock_2.assert();
-        get_changeset_mock_2.delete();
+        get_changeset_mock.assert_hits(2);
+        get_changeset_mock.delete();
     }
 
     #[test]
@@ -1129,4 +3027,77 @@ mod tests {
             mock.delete();
         }
     }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_sync_records_tracing_spans() {
+        use std::collections::HashMap;
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record as Values};
+        use tracing::Subscriber;
+        use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+        type Spans = Vec<(Id, &'static str, HashMap<&'static str, String>)>;
+
+        struct FieldsVisitor<'a>(&'a mut HashMap<&'static str, String>);
+
+        impl Visit for FieldsVisitor<'_> {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                self.0.insert(field.name(), format!("{:?}", value));
+            }
+        }
+
+        struct SpansCapture(Arc<Mutex<Spans>>);
+
+        impl<S: Subscriber> Layer<S> for SpansCapture {
+            fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, _: Context<'_, S>) {
+                let mut fields = HashMap::new();
+                attrs.record(&mut FieldsVisitor(&mut fields));
+                let name = attrs.metadata().name();
+                self.0.lock().unwrap().push((id.clone(), name, fields));
+            }
+
+            fn on_record(&self, id: &Id, values: &Values<'_>, _: Context<'_, S>) {
+                let mut spans = self.0.lock().unwrap();
+                if let Some((_, _, fields)) = spans.iter_mut().rev().find(|(i, _, _)| i == id) {
+                    values.record(&mut FieldsVisitor(fields));
+                }
+            }
+        }
+
+        init();
+
+        let mock_server = MockServer::start();
+        let body = r#"{"metadata": {}, "changes": [{"id": "record-1", "last_modified": 42}], "timestamp": 42}"#;
+        let mut get_changeset_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/url-classifier-skip-urls/changeset");
+            then.body(body);
+        });
+
+        let mut client = Client::builder()
+            .server_url(mock_server.url(""))
+            .collection_name("url-classifier-skip-urls")
+            .verifier(Box::new(DummyVerifier {}))
+            .build()
+            .unwrap();
+
+        let spans = Arc::new(Mutex::new(Spans::new()));
+        let subscriber = tracing_subscriber::registry().with(SpansCapture(spans.clone()));
+        tracing::subscriber::with_default(subscriber, || client.sync(42).unwrap());
+
+        let spans = spans.lock().unwrap();
+        let span = |name: &str| {
+            let found = spans.iter().find(|(_, n, _)| *n == name);
+            found.map(|(_, _, fields)| fields).unwrap()
+        };
+        let sync = span("sync");
+        assert_eq!(sync["bucket"], "main");
+        assert_eq!(sync["collection"], "url-classifier-skip-urls");
+        assert_eq!(sync["remote_timestamp"], "42");
+        assert_eq!(span("get_changeset")["size"], body.len().to_string());
+        assert_eq!(span("verify")["timestamp"], "42");
+
+        get_changeset_mock.assert();
+        get_changeset_mock.delete();
+    }
 }
@@ -0,0 +1,399 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use super::migrations::{migrate, Migration, MIGRATIONS, SCHEMA_VERSION};
+use super::storage::StorageError;
+use super::Collection;
+use log::debug;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+// Stored collections start with the format version, followed by the codec id, the
+// schema version, and the SHA-256 checksum of the header and payload. The previous
+// formats had no checksum, and the first one had no schema version either. Values
+// without this header were stored as plain JSON, which never starts with these bytes.
+const FORMAT_VERSION: u8 = 3;
+const FORMAT_VERSION_WITHOUT_CHECKSUM: u8 = 2;
+const FORMAT_VERSION_WITHOUT_SCHEMA: u8 = 1;
+const HEADER_LEN: usize = 3;
+const CHECKSUM_LEN: usize = 32;
+
+const JSON_CODEC_ID: u8 = 1;
+#[cfg(feature = "cbor")]
+const CBOR_CODEC_ID: u8 = 2;
+
+/// A trait for serializing the collections into storage values.
+///
+/// Stored values are prefixed with a header identifying the codec, so that values
+/// written with one of the built-in codecs can still be read after switching to another.
+///
+/// # How can I implement ```Codec```?
+/// ```rust
+/// # use remote_settings_client::{Codec, StorageError};
+/// # use remote_settings_client::client::Collection;
+/// struct MyCodec {}
+///
+/// impl Codec for MyCodec {
+///     fn id(&self) -> u8 {
+///         100
+///     }
+///
+///     fn encode(&self, collection: &Collection) -> Result<Vec<u8>, StorageError> {
+///         serde_json::to_vec_pretty(collection).map_err(|err| StorageError::WriteError(err.to_string()))
+///     }
+///
+///     fn decode(&self, bytes: &[u8]) -> Result<Collection, StorageError> {
+///         serde_json::from_slice(bytes).map_err(|err| StorageError::ReadError(err.to_string()))
+///     }
+/// }
+/// ```
+pub trait Codec: Send {
+    /// Identifier of the codec, written in the header of stored values.
+    ///
+    /// Values from 0 to 99 are reserved for the built-in codecs.
+    fn id(&self) -> u8;
+
+    /// Serialize the collection.
+    ///
+    /// # Errors
+    /// If the collection cannot be serialized, a [`StorageError::WriteError`] is returned.
+    fn encode(&self, collection: &Collection) -> Result<Vec<u8>, StorageError>;
+
+    /// Deserialize a collection.
+    ///
+    /// # Errors
+    /// If the bytes cannot be deserialized, a [`StorageError::ReadError`] is returned.
+    fn decode(&self, bytes: &[u8]) -> Result<Collection, StorageError>;
+
+    /// Deserialize a collection stored with a previous schema version, in order to migrate it.
+    ///
+    /// By default, collections of previous schema versions are not migrated but synced again.
+    ///
+    /// # Errors
+    /// If the bytes cannot be deserialized, a [`StorageError::ReadError`] is returned.
+    ///
+    /// If migrations are not supported, a [`StorageError::UnsupportedSchema`] is returned.
+    fn decode_value(&self, _bytes: &[u8]) -> Result<Value, StorageError> {
+        Err(StorageError::UnsupportedSchema(format!(
+            "codec {} does not support migrations",
+            self.id()
+        )))
+    }
+}
+
+/// Default `Codec`, which stores collections as JSON.
+pub struct JsonCodec {}
+
+impl Codec for JsonCodec {
+    fn id(&self) -> u8 {
+        JSON_CODEC_ID
+    }
+
+    fn encode(&self, collection: &Collection) -> Result<Vec<u8>, StorageError> {
+        serde_json::to_vec(collection).map_err(|err| {
+            StorageError::WriteError(format!("cannot serialize collection: {}", err))
+        })
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Collection, StorageError> {
+        serde_json::from_slice(bytes).map_err(|err| {
+            StorageError::ReadError(format!("cannot deserialize collection: {}", err))
+        })
+    }
+
+    fn decode_value(&self, bytes: &[u8]) -> Result<Value, StorageError> {
+        serde_json::from_slice(bytes).map_err(|err| {
+            StorageError::ReadError(format!("cannot deserialize collection: {}", err))
+        })
+    }
+}
+
+/// A `Codec` which stores collections as [CBOR](https://cbor.io), more compact and faster to parse than JSON.
+///
+/// With the `cbor` feature.
+#[cfg(feature = "cbor")]
+pub struct CborCodec {}
+
+#[cfg(feature = "cbor")]
+impl Codec for CborCodec {
+    fn id(&self) -> u8 {
+        CBOR_CODEC_ID
+    }
+
+    fn encode(&self, collection: &Collection) -> Result<Vec<u8>, StorageError> {
+        serde_cbor::to_vec(collection).map_err(|err| {
+            StorageError::WriteError(format!("cannot serialize collection: {}", err))
+        })
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Collection, StorageError> {
+        serde_cbor::from_slice(bytes).map_err(|err| {
+            StorageError::ReadError(format!("cannot deserialize collection: {}", err))
+        })
+    }
+
+    fn decode_value(&self, bytes: &[u8]) -> Result<Value, StorageError> {
+        serde_cbor::from_slice(bytes).map_err(|err| {
+            StorageError::ReadError(format!("cannot deserialize collection: {}", err))
+        })
+    }
+}
+
+/// Serialize the collection with the specified codec, prefixed with the header and checksum.
+pub(crate) fn encode_collection(
+    codec: &dyn Codec,
+    collection: &Collection,
+) -> Result<Vec<u8>, StorageError> {
+    let payload = codec.encode(collection)?;
+    let header = [FORMAT_VERSION, codec.id(), SCHEMA_VERSION];
+    let mut bytes = Vec::with_capacity(HEADER_LEN + CHECKSUM_LEN + payload.len());
+    bytes.extend_from_slice(&header);
+    bytes.extend_from_slice(&checksum(&header, &payload));
+    bytes.extend(payload);
+    Ok(bytes)
+}
+
+/// Deserialize a stored collection, with the codec identified in its header, and
+/// migrate it if it was stored with a previous schema version.
+///
+/// # Errors
+/// If the checksum does not match, a [`StorageError::CorruptedData`] is returned.
+pub(crate) fn decode_collection(
+    codec: &dyn Codec,
+    bytes: &[u8],
+) -> Result<Collection, StorageError> {
+    decode_and_migrate(codec, bytes, SCHEMA_VERSION, MIGRATIONS)
+}
+
+/// Return the checksum of a collection stored with the current format. It identifies the
+/// content, and is verified when the collection is decoded.
+pub(crate) fn stored_checksum(bytes: &[u8]) -> Option<[u8; CHECKSUM_LEN]> {
+    match bytes.first() {
+        Some(&FORMAT_VERSION) if bytes.len() >= HEADER_LEN + CHECKSUM_LEN => {
+            let mut checksum = [0; CHECKSUM_LEN];
+            checksum.copy_from_slice(&bytes[HEADER_LEN..HEADER_LEN + CHECKSUM_LEN]);
+            Some(checksum)
+        }
+        _ => None,
+    }
+}
+
+fn decode_and_migrate(
+    codec: &dyn Codec,
+    bytes: &[u8],
+    schema_version: u8,
+    migrations: &[(u8, Migration)],
+) -> Result<Collection, StorageError> {
+    let (codec, stored_version, payload) = match bytes.first() {
+        Some(&FORMAT_VERSION) if bytes.len() >= HEADER_LEN + CHECKSUM_LEN => {
+            let (header, rest) = bytes.split_at(HEADER_LEN);
+            let (stored_checksum, payload) = rest.split_at(CHECKSUM_LEN);
+            if stored_checksum != checksum(header, payload).as_slice() {
+                return Err(StorageError::CorruptedData("checksum mismatch".to_string()));
+            }
+            (codec_for(codec, header[1])?, header[2], payload)
+        }
+        Some(&FORMAT_VERSION_WITHOUT_CHECKSUM) if bytes.len() >= HEADER_LEN => {
+            (codec_for(codec, bytes[1])?, bytes[2], &bytes[HEADER_LEN..])
+        }
+        Some(&FORMAT_VERSION_WITHOUT_SCHEMA) if bytes.len() >= 2 => {
+            (codec_for(codec, bytes[1])?, 1, &bytes[2..])
+        }
+        Some(&FORMAT_VERSION)
+        | Some(&FORMAT_VERSION_WITHOUT_CHECKSUM)
+        | Some(&FORMAT_VERSION_WITHOUT_SCHEMA) => {
+            return Err(StorageError::ReadError(
+                "cannot deserialize collection: missing header".to_string(),
+            ));
+        }
+        _ => {
+            debug!("Read collection stored without header as JSON");
+            (&JsonCodec {} as &dyn Codec, 1, bytes)
+        }
+    };
+    if stored_version == schema_version {
+        return codec.decode(payload);
+    }
+    let value = migrate(
+        codec.decode_value(payload)?,
+        stored_version,
+        schema_version,
+        migrations,
+    )?;
+    serde_json::from_value(value).map_err(|err| {
+        StorageError::UnsupportedSchema(format!("cannot deserialize migrated collection: {}", err))
+    })
+}
+
+fn checksum(header: &[u8], payload: &[u8]) -> [u8; CHECKSUM_LEN] {
+    Sha256::new().chain(header).chain(payload).finalize().into()
+}
+
+/// Return the codec of a stored value, from its id.
+fn codec_for(codec: &dyn Codec, id: u8) -> Result<&dyn Codec, StorageError> {
+    match id {
+        id if id == codec.id() => Ok(codec),
+        JSON_CODEC_ID => Ok(&JsonCodec {}),
+        #[cfg(feature = "cbor")]
+        CBOR_CODEC_ID => Ok(&CborCodec {}),
+        id => Err(StorageError::UnsupportedSchema(format!(
+            "cannot deserialize collection: unknown codec {}",
+            id
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_and_migrate, decode_collection, encode_collection, stored_checksum, JsonCodec,
+    };
+    use crate::client::migrations::Migration;
+    use crate::client::testing::test_collection;
+    use crate::client::{Collection, Record, StorageError};
+    use serde_json::json;
+
+    fn collection() -> Collection {
+        let records = vec![Record::new(json!({"id": "a", "last_modified": 42}))];
+        test_collection("cfr", 42, records)
+    }
+
+    #[test]
+    fn test_encode_and_decode() {
+        let bytes = encode_collection(&JsonCodec {}, &collection()).unwrap();
+
+        assert_eq!(&bytes[..3], b"\x03\x01\x01");
+        assert_eq!(bytes[35], b'{');
+        assert_eq!(
+            decode_collection(&JsonCodec {}, &bytes).unwrap(),
+            collection()
+        );
+    }
+
+    #[test]
+    fn test_decode_json_without_header() {
+        let bytes = serde_json::to_vec(&collection()).unwrap();
+
+        assert_eq!(
+            decode_collection(&JsonCodec {}, &bytes).unwrap(),
+            collection()
+        );
+    }
+
+    #[test]
+    fn test_decode_unknown_format() {
+        for bytes in &[
+            &b""[..],
+            b"\x01",
+            b"\x02\x01{}",
+            b"\x01\x01{",
+            b"\x02\x01",
+            b"\x03\x01\x01{}",
+        ] {
+            assert!(matches!(
+                decode_collection(&JsonCodec {}, bytes),
+                Err(StorageError::ReadError(_))
+            ));
+        }
+        // Unknown codecs and newer schema versions.
+        for bytes in &[&b"\x01\x63{}"[..], b"\x02\x01\x09{}"] {
+            assert!(matches!(
+                decode_collection(&JsonCodec {}, bytes),
+                Err(StorageError::UnsupportedSchema(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_decode_corrupted_data() {
+        let bytes = encode_collection(&JsonCodec {}, &collection()).unwrap();
+
+        for position in &[1, 2, 10, bytes.len() - 3] {
+            let mut corrupted = bytes.clone();
+            corrupted[*position] ^= 0x04;
+            assert!(matches!(
+                decode_collection(&JsonCodec {}, &corrupted),
+                Err(StorageError::CorruptedData(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_stored_checksum() {
+        let bytes = encode_collection(&JsonCodec {}, &collection()).unwrap();
+        let mut other = collection();
+        other.timestamp = 43;
+
+        assert_eq!(stored_checksum(&bytes).unwrap()[..], bytes[3..35]);
+        assert_ne!(
+            stored_checksum(&encode_collection(&JsonCodec {}, &other).unwrap()),
+            stored_checksum(&bytes)
+        );
+        assert_eq!(stored_checksum(b"\x02\x01\x01{}"), None);
+    }
+
+    #[test]
+    fn test_decode_without_checksum() {
+        let mut bytes = b"\x02\x01\x01".to_vec();
+        bytes.extend(serde_json::to_vec(&collection()).unwrap());
+
+        assert_eq!(
+            decode_collection(&JsonCodec {}, &bytes).unwrap(),
+            collection()
+        );
+    }
+
+    #[test]
+    fn test_decode_without_schema_version() {
+        let mut bytes = b"\x01\x01".to_vec();
+        bytes.extend(serde_json::to_vec(&collection()).unwrap());
+
+        assert_eq!(
+            decode_collection(&JsonCodec {}, &bytes).unwrap(),
+            collection()
+        );
+    }
+
+    #[test]
+    fn test_decode_previous_schema_version() {
+        fn rename_data(mut value: serde_json::Value) -> Result<serde_json::Value, StorageError> {
+            value["records"] = value["data"].take();
+            Ok(value)
+        }
+        const MIGRATIONS: &[(u8, Migration)] = &[(1, rename_data)];
+
+        let mut value = serde_json::to_value(collection()).unwrap();
+        value["data"] = value["records"].take();
+        let mut bytes = b"\x02\x01\x01".to_vec();
+        bytes.extend(serde_json::to_vec(&value).unwrap());
+
+        assert_eq!(
+            decode_and_migrate(&JsonCodec {}, &bytes, 2, MIGRATIONS).unwrap(),
+            collection()
+        );
+        assert!(matches!(
+            decode_and_migrate(&JsonCodec {}, &bytes, 3, MIGRATIONS),
+            Err(StorageError::UnsupportedSchema(_))
+        ));
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_switch_codec() {
+        use super::CborCodec;
+
+        let json = encode_collection(&JsonCodec {}, &collection()).unwrap();
+        let cbor = encode_collection(&CborCodec {}, &collection()).unwrap();
+
+        assert!(cbor.len() < json.len());
+        assert_eq!(
+            decode_collection(&CborCodec {}, &json).unwrap(),
+            collection()
+        );
+        assert_eq!(
+            decode_collection(&JsonCodec {}, &cbor).unwrap(),
+            collection()
+        );
+    }
+}
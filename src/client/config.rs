@@ -0,0 +1,342 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use super::{ClientBuilder, FileStorage};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Prefix of the environment variables that override the configuration values.
+pub const ENV_PREFIX: &str = "REMOTE_SETTINGS_";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("cannot read configuration file: {0}")]
+    ReadError(#[from] std::io::Error),
+    #[error("unsupported configuration format: {0}")]
+    UnsupportedFormat(String),
+    #[error("TOML configuration could not be parsed: {0}")]
+    InvalidTOML(#[from] toml::de::Error),
+    #[error("JSON configuration could not be parsed: {0}")]
+    InvalidJSON(#[from] serde_json::Error),
+    #[error("bad value for {name}: {value:?}")]
+    InvalidValue { name: String, value: String },
+}
+
+/// Configuration of a [`Client`](super::Client), as read from a file or from the environment.
+///
+/// Every field is optional: missing values keep the defaults of the [`ClientBuilder`].
+///
+/// # Examples
+/// ```rust
+/// # use remote_settings_client::ClientConfig;
+/// # fn main() {
+/// let config = ClientConfig::from_toml_str(r#"
+///     server_url = "https://settings.stage.mozaws.net/v1"
+///     collection_name = "cid"
+///     storage_folder = "/tmp/remote-settings"
+/// "#).unwrap();
+///
+/// let client = config
+///     .with_env()
+///     .unwrap()
+///     .into_builder()
+///     .build()
+///     .unwrap();
+/// # }
+/// ```
+///
+/// ## Environment variables
+///
+/// | Variable                          | Field             |
+/// |-----------------------------------|-------------------|
+/// | `REMOTE_SETTINGS_SERVER_URL`      | `server_url`      |
+/// | `REMOTE_SETTINGS_BUCKET_NAME`     | `bucket_name`     |
+/// | `REMOTE_SETTINGS_COLLECTION_NAME` | `collection_name` |
+/// | `REMOTE_SETTINGS_SIGNER_NAME`     | `signer_name`     |
+/// | `REMOTE_SETTINGS_CERT_ROOT_HASH`  | `cert_root_hash`  |
+/// | `REMOTE_SETTINGS_STORAGE_FOLDER`  | `storage_folder`  |
+/// | `REMOTE_SETTINGS_SYNC_IF_EMPTY`   | `sync_if_empty`   |
+/// | `REMOTE_SETTINGS_TRUST_LOCAL`     | `trust_local`     |
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientConfig {
+    pub server_url: Option<String>,
+    pub bucket_name: Option<String>,
+    pub collection_name: Option<String>,
+    pub signer_name: Option<String>,
+    pub cert_root_hash: Option<String>,
+    pub storage_folder: Option<PathBuf>,
+    pub sync_if_empty: Option<bool>,
+    pub trust_local: Option<bool>,
+}
+
+impl ClientConfig {
+    /// Parse a configuration in TOML format.
+    pub fn from_toml_str(content: &str) -> Result<Self, ConfigError> {
+        Ok(toml::from_str(content)?)
+    }
+
+    /// Parse a configuration in JSON format.
+    pub fn from_json_str(content: &str) -> Result<Self, ConfigError> {
+        Ok(serde_json::from_str(content)?)
+    }
+
+    /// Read a configuration file. The format is picked from the file extension (`.toml` or `.json`).
+    ///
+    /// # Errors
+    /// If the file cannot be read, a [`ConfigError::ReadError`] is returned.
+    ///
+    /// If the extension is neither `toml` nor `json`, a [`ConfigError::UnsupportedFormat`] is returned.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(OsStr::to_str)
+            .unwrap_or("")
+            .to_lowercase();
+        match extension.as_str() {
+            "toml" => Self::from_toml_str(&fs::read_to_string(path)?),
+            "json" => Self::from_json_str(&fs::read_to_string(path)?),
+            _ => Err(ConfigError::UnsupportedFormat(path.display().to_string())),
+        }
+    }
+
+    /// Build a configuration from the `REMOTE_SETTINGS_*` environment variables only.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::default().with_env()
+    }
+
+    /// Override the configuration values with the `REMOTE_SETTINGS_*` environment variables.
+    ///
+    /// # Errors
+    /// If a boolean variable cannot be parsed, a [`ConfigError::InvalidValue`] is returned.
+    pub fn with_env(self) -> Result<Self, ConfigError> {
+        self.with_vars(std::env::vars())
+    }
+
+    fn with_vars<I>(mut self, vars: I) -> Result<Self, ConfigError>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        for (name, value) in vars {
+            let field = match name.strip_prefix(ENV_PREFIX) {
+                Some(field) => field,
+                None => continue,
+            };
+            match field {
+                "SERVER_URL" => self.server_url = Some(value),
+                "BUCKET_NAME" => self.bucket_name = Some(value),
+                "COLLECTION_NAME" => self.collection_name = Some(value),
+                "SIGNER_NAME" => self.signer_name = Some(value),
+                "CERT_ROOT_HASH" => self.cert_root_hash = Some(value),
+                "STORAGE_FOLDER" => self.storage_folder = Some(PathBuf::from(value)),
+                "SYNC_IF_EMPTY" => self.sync_if_empty = Some(parse_bool(&name, &value)?),
+                "TRUST_LOCAL" => self.trust_local = Some(parse_bool(&name, &value)?),
+                _ => {}
+            }
+        }
+        Ok(self)
+    }
+
+    /// Turn this configuration into a [`ClientBuilder`].
+    ///
+    /// When `storage_folder` is set, a [`FileStorage`] in this folder is used.
+    pub fn into_builder(self) -> ClientBuilder {
+        ClientBuilder::from(self)
+    }
+}
+
+impl From<ClientConfig> for ClientBuilder {
+    fn from(config: ClientConfig) -> Self {
+        let mut builder = ClientBuilder::default();
+        if let Some(v) = config.server_url {
+            builder = builder.server_url(v);
+        }
+        if let Some(v) = config.bucket_name {
+            builder = builder.bucket_name(v);
+        }
+        if let Some(v) = config.collection_name {
+            builder = builder.collection_name(v);
+        }
+        if let Some(v) = config.signer_name {
+            builder = builder.signer_name(v);
+        }
+        if let Some(v) = config.cert_root_hash {
+            builder = builder.cert_root_hash(v);
+        }
+        if let Some(folder) = config.storage_folder {
+            builder = builder.storage(Box::new(FileStorage {
+                folder,
+                ..FileStorage::default()
+            }));
+        }
+        if let Some(v) = config.sync_if_empty {
+            builder = builder.sync_if_empty(v);
+        }
+        if let Some(v) = config.trust_local {
+            builder = builder.trust_local(v);
+        }
+        builder
+    }
+}
+
+fn parse_bool(name: &str, value: &str) -> Result<bool, ConfigError> {
+    match value.to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(ConfigError::InvalidValue {
+            name: name.to_string(),
+            value: value.to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClientConfig, ConfigError};
+    use std::fs::{remove_file, write};
+    use std::path::PathBuf;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_from_toml() {
+        let config = ClientConfig::from_toml_str(
+            r#"
+            server_url = "https://settings.stage.mozaws.net/v1"
+            bucket_name = "main-preview"
+            collection_name = "cfr"
+            storage_folder = "/tmp"
+            trust_local = false
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.server_url.as_deref(),
+            Some("https://settings.stage.mozaws.net/v1")
+        );
+        assert_eq!(config.bucket_name.as_deref(), Some("main-preview"));
+        assert_eq!(config.collection_name.as_deref(), Some("cfr"));
+        assert_eq!(config.storage_folder, Some(PathBuf::from("/tmp")));
+        assert_eq!(config.trust_local, Some(false));
+        assert_eq!(config.sync_if_empty, None);
+    }
+
+    #[test]
+    fn test_from_json() {
+        let config = ClientConfig::from_json_str(
+            r#"{
+                "collection_name": "cfr",
+                "signer_name": "onecrl.content-signature.mozilla.org",
+                "sync_if_empty": false
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.collection_name.as_deref(), Some("cfr"));
+        assert_eq!(
+            config.signer_name.as_deref(),
+            Some("onecrl.content-signature.mozilla.org")
+        );
+        assert_eq!(config.sync_if_empty, Some(false));
+    }
+
+    #[test]
+    fn test_unknown_field() {
+        let err = ClientConfig::from_json_str(r#"{"colection_name": "cfr"}"#).unwrap_err();
+
+        assert!(matches!(err, ConfigError::InvalidJSON(_)));
+    }
+
+    #[test]
+    fn test_from_file() {
+        write("./config-test.toml", "collection_name = \"cfr\"").unwrap();
+        write("./config-test.json", "{\"collection_name\": \"pocket\"}").unwrap();
+
+        let toml = ClientConfig::from_file("./config-test.toml").unwrap();
+        let json = ClientConfig::from_file("./config-test.json").unwrap();
+        let err = ClientConfig::from_file("./config-test.yaml").unwrap_err();
+
+        assert_eq!(toml.collection_name.as_deref(), Some("cfr"));
+        assert_eq!(json.collection_name.as_deref(), Some("pocket"));
+        assert_eq!(
+            err.to_string(),
+            "unsupported configuration format: ./config-test.yaml"
+        );
+
+        remove_file("./config-test.toml").unwrap();
+        remove_file("./config-test.json").unwrap();
+    }
+
+    #[test]
+    fn test_env_overrides_values() {
+        let config = ClientConfig {
+            server_url: Some("https://settings.stage.mozaws.net/v1".to_owned()),
+            collection_name: Some("cfr".to_owned()),
+            ..ClientConfig::default()
+        }
+        .with_vars(vars(&[
+            ("REMOTE_SETTINGS_SERVER_URL", "http://localhost:8888/v1"),
+            ("REMOTE_SETTINGS_TRUST_LOCAL", "0"),
+            ("REMOTE_SETTINGS_STORAGE_FOLDER", "/var/cache"),
+            ("HOME", "/root"),
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            config.server_url.as_deref(),
+            Some("http://localhost:8888/v1")
+        );
+        assert_eq!(config.collection_name.as_deref(), Some("cfr"));
+        assert_eq!(config.trust_local, Some(false));
+        assert_eq!(config.storage_folder, Some(PathBuf::from("/var/cache")));
+    }
+
+    #[test]
+    fn test_env_bad_boolean() {
+        let err = ClientConfig::default()
+            .with_vars(vars(&[("REMOTE_SETTINGS_SYNC_IF_EMPTY", "maybe")]))
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "bad value for REMOTE_SETTINGS_SYNC_IF_EMPTY: \"maybe\""
+        );
+    }
+
+    #[test]
+    fn test_into_builder() {
+        let client = ClientConfig {
+            bucket_name: Some("main-preview".to_owned()),
+            collection_name: Some("cfr".to_owned()),
+            sync_if_empty: Some(false),
+            ..ClientConfig::default()
+        }
+        .into_builder()
+        .build()
+        .unwrap();
+
+        assert_eq!(client.bucket_name, "main-preview");
+        assert_eq!(client.collection_name, "cfr");
+        assert_eq!(client.server_url, super::super::DEFAULT_SERVER_URL);
+        assert!(!client.sync_if_empty);
+        assert!(client.trust_local);
+    }
+
+    #[test]
+    fn test_into_builder_requires_collection() {
+        let err = ClientConfig::default().into_builder().build().unwrap_err();
+
+        assert_eq!(err.to_string(), "`collection_name` must be initialized");
+    }
+}
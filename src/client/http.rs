@@ -0,0 +1,135 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use super::kinto_http::Alert;
+use super::AlertHandler;
+use std::time::Duration;
+use viaduct::{Error as ViaductError, Request, Response};
+
+/// A trait for giving the client a custom HTTP transport.
+///
+/// Every request made by the client (changesets, `monitor/changes`, certificate chains)
+/// goes through the configured `HttpClient`.
+///
+/// # How can I implement ```HttpClient```?
+/// ```rust
+/// # use remote_settings_client::{Client, HttpClient};
+/// # use viaduct::{Error, Request, Response};
+/// struct LoggingHttpClient {}
+///
+/// impl HttpClient for LoggingHttpClient {
+///     fn send(&self, request: Request) -> Result<Response, Error> {
+///         println!("{} {}", request.method, request.url);
+///         request.send()
+///     }
+/// }
+///
+/// # fn main() {
+/// let client = Client::builder()
+///    .collection_name("cid")
+///    .http_client(Box::new(LoggingHttpClient {}))
+///    .build();
+/// # }
+/// ```
+pub trait HttpClient: Send + Sync {
+    /// Send the request and return the server response.
+    ///
+    /// # Errors
+    /// If the request could not be sent or no response was received, a [`viaduct::Error`] is returned.
+    /// Responses with error status codes are not considered as errors.
+    fn send(&self, request: Request) -> Result<Response, ViaductError>;
+
+    /// Send the request, giving up if connecting or reading the response takes longer than
+    /// the specified timeouts.
+    ///
+    /// The default implementation ignores the timeouts and calls [`HttpClient::send`].
+    /// Implement it when the underlying transport supports timeouts, along with
+    /// [`HttpClient::supports_timeouts`].
+    fn send_with_timeouts(
+        &self,
+        request: Request,
+        _connect_timeout: Option<Duration>,
+        _read_timeout: Option<Duration>,
+    ) -> Result<Response, ViaductError> {
+        self.send(request)
+    }
+
+    /// Whether the timeouts passed to [`HttpClient::send_with_timeouts`] are applied.
+    ///
+    /// Clients configured with timeouts fail to build if their `HttpClient` does not support them.
+    fn supports_timeouts(&self) -> bool {
+        false
+    }
+}
+
+/// Default `HttpClient`, relying on the backend set via [`viaduct::set_backend`].
+///
+/// Timeouts are the ones of the viaduct backend, and are not configurable per request:
+/// a `Client` with `.connect_timeout()` or `.read_timeout()` requires another `HttpClient`.
+pub struct ViaductHttpClient {}
+
+impl HttpClient for ViaductHttpClient {
+    fn send(&self, request: Request) -> Result<Response, ViaductError> {
+        request.send()
+    }
+}
+
+/// Options applied to every request sent by the [`Client`](crate::Client).
+///
+/// The default transport, [`ViaductHttpClient`], does not support timeouts: a client
+/// with `connect_timeout` or `read_timeout` fails to build unless another
+/// [`HttpClient`] that supports them is set.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RequestOptions {
+    pub user_agent: Option<String>,
+    pub headers: Vec<(String, String)>,
+    pub connect_timeout: Option<Duration>,
+    pub read_timeout: Option<Duration>,
+}
+
+impl RequestOptions {
+    /// Wraps the specified `HttpClient` in order to apply these options on its requests.
+    pub(crate) fn apply<'a>(&'a self, http: &'a dyn HttpClient) -> ConfiguredHttpClient<'a> {
+        ConfiguredHttpClient {
+            http,
+            options: self,
+            alert_handler: None,
+        }
+    }
+}
+
+pub(crate) struct ConfiguredHttpClient<'a> {
+    http: &'a dyn HttpClient,
+    options: &'a RequestOptions,
+    alert_handler: Option<&'a dyn AlertHandler>,
+}
+
+impl<'a> ConfiguredHttpClient<'a> {
+    /// Pass the alerts of every response (including `304 Not Modified`) to the specified handler.
+    pub(crate) fn with_alert_handler(mut self, handler: Option<&'a dyn AlertHandler>) -> Self {
+        self.alert_handler = handler;
+        self
+    }
+}
+
+impl HttpClient for ConfiguredHttpClient<'_> {
+    fn send(&self, mut request: Request) -> Result<Response, ViaductError> {
+        if let Some(ref user_agent) = self.options.user_agent {
+            request = request.header("User-Agent", user_agent.as_str())?;
+        }
+        for (name, value) in &self.options.headers {
+            request = request.header(name.clone(), value.as_str())?;
+        }
+        let response = self.http.send_with_timeouts(
+            request,
+            self.options.connect_timeout,
+            self.options.read_timeout,
+        )?;
+        if let (Some(handler), Some(alert)) = (self.alert_handler, Alert::from_response(&response))
+        {
+            handler.on_alert(&alert);
+        }
+        Ok(response)
+    }
+}
@@ -2,8 +2,12 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use log::{debug, info};
+use super::http::HttpClient;
+use crate::client::Record;
+use log::{debug, info, warn};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use thiserror::Error;
 use url::{ParseError as URLParseError, Url};
 use viaduct::{Error as ViaductError, Request, Response};
@@ -15,12 +19,164 @@ struct KintoPluralResponse<T> {
     data: Vec<T>,
 }
 
+#[derive(Deserialize, Debug)]
+pub(crate) struct KintoSingularResponse<T> {
+    pub data: T,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ChangesetResponse {
     pub metadata: KintoObject,
     pub changes: Vec<KintoObject>,
     pub timestamp: u64,
     pub backoff: Option<u64>,
+    #[serde(skip)]
+    pub validators: CacheValidators,
+    #[serde(skip)]
+    pub alert: Option<Alert>,
+    #[serde(skip)]
+    pub size: Option<u64>,
+}
+
+/// Result of a (possibly conditional) changeset request.
+#[derive(Debug)]
+pub enum Changeset {
+    Modified(ChangesetResponse),
+    /// The server content was not modified since the response the validators come from.
+    NotModified {
+        backoff: Option<u64>,
+    },
+}
+
+/// Deprecation or end-of-service notice, sent by the server in the `Alert` header.
+///
+/// See <https://docs.kinto-storage.org/en/stable/api/1.x/deprecation.html>
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub code: String,
+    pub message: Option<String>,
+    pub url: Option<String>,
+}
+
+impl Alert {
+    pub(crate) fn from_response(response: &Response) -> Option<Self> {
+        let header = response.headers.get("alert")?;
+        match serde_json::from_str(header) {
+            Ok(alert) => Some(alert),
+            Err(err) => {
+                debug!("Ignore malformed Alert header {:?}: {}", header, err);
+                None
+            }
+        }
+    }
+}
+
+/// Validators of a previous response, sent back to the server to make conditional requests.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CacheValidators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl CacheValidators {
+    fn from_response(response: &Response) -> Self {
+        CacheValidators {
+            etag: response.headers.get("etag").map(String::from),
+            last_modified: response.headers.get("last-modified").map(String::from),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// Information about the server, as returned by its root URL.
+///
+/// See <https://docs.kinto-storage.org/en/stable/api/1.x/utilities.html#api-utilities>
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ServerInfo {
+    pub project_name: String,
+    pub project_version: String,
+    pub http_api_version: String,
+    pub url: String,
+    /// End-of-service date (`YYYY-MM-DD`) of this server version, if any.
+    #[serde(default)]
+    pub eos: Option<String>,
+    #[serde(default)]
+    pub eos_message: Option<String>,
+    #[serde(default)]
+    pub eos_url: Option<String>,
+    #[serde(default)]
+    pub capabilities: Capabilities,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Capabilities {
+    pub attachments: Option<AttachmentsCapability>,
+    pub changes: Option<ChangesCapability>,
+    pub signer: Option<SignerCapability>,
+    /// Other capabilities, not typed by this crate.
+    #[serde(flatten)]
+    pub others: HashMap<String, KintoObject>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AttachmentsCapability {
+    pub base_url: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChangesCapability {
+    /// The buckets and collections whose changes are tracked (eg. `/buckets/main`).
+    #[serde(default)]
+    pub collections: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SignerCapability {
+    #[serde(default)]
+    pub to_review_enabled: bool,
+    #[serde(default)]
+    pub group_check_enabled: bool,
+    /// The source, preview and destination of each signed resource.
+    #[serde(default)]
+    pub resources: Vec<KintoObject>,
+}
+
+impl ServerInfo {
+    /// Return true if the server has the specified capability.
+    pub fn has_capability(&self, name: &str) -> bool {
+        match name {
+            "attachments" => self.capabilities.attachments.is_some(),
+            "changes" => self.capabilities.changes.is_some(),
+            "signer" => self.capabilities.signer.is_some(),
+            _ => self.capabilities.others.contains_key(name),
+        }
+    }
+
+    /// Return the base URL of attachments, if the server supports them.
+    pub fn attachments_base_url(&self) -> Option<&str> {
+        self.capabilities
+            .attachments
+            .as_ref()
+            .map(|a| a.base_url.as_str())
+    }
+}
+
+/// Content of the last `monitor/changes` response, kept to make conditional requests.
+#[derive(Debug, Default)]
+pub struct LatestChanges {
+    validators: CacheValidators,
+    timestamps: HashMap<(String, String), KintoObject>,
+    backoff: Option<u64>,
+}
+
+impl LatestChanges {
+    /// Number of seconds the server asked clients to back off in the last response.
+    pub fn backoff(&self) -> Option<u64> {
+        self.backoff
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -42,6 +198,11 @@ pub enum KintoError {
     },
     #[error("the server responded with unexpected content on {} {}: HTTP {}", response.request_method, response.url, response.status)]
     UnexpectedResponse { response: Response },
+    #[error("precondition failed on {} {}: {}", response.request_method, response.url, info)]
+    PreconditionFailed {
+        response: Response,
+        info: ErrorResponse,
+    },
     #[error("invalid request on {} {}: {}", response.request_method, response.url, info)]
     ClientRequestError {
         response: Response,
@@ -51,6 +212,10 @@ pub enum KintoError {
     InvalidChangesetTimestamp(String),
     #[error("changeset content could not be parsed: {0}")]
     InvalidChangesetBody(#[from] serde_json::Error),
+    #[error("server info could not be parsed: {0}")]
+    InvalidServerInfo(serde_json::Error),
+    #[error("response content could not be parsed: {0}")]
+    InvalidResponseBody(serde_json::Error),
     #[error("unknown collection: {bucket}/{collection}")]
     UnknownCollection { bucket: String, collection: String },
     #[error("HTTP backend issue: {0}")]
@@ -71,23 +236,72 @@ impl std::fmt::Display for ErrorResponse {
     }
 }
 
-pub fn get_latest_change_timestamp(server: &str, bid: &str, cid: &str) -> Result<u64> {
+/// Fetches the server information from its root URL.
+pub fn get_server_info(http: &dyn HttpClient, server: &str) -> Result<ServerInfo> {
+    let url = format!("{}/", server.trim_end_matches('/'));
+    info!("Fetch {}...", url);
+    let response = check_response(http.send(Request::get(Url::parse(&url)?))?)?;
+
+    response.json().map_err(KintoError::InvalidServerInfo)
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip(http, latest), err)
+)]
+pub fn get_latest_change_timestamp(
+    http: &dyn HttpClient,
+    server: &str,
+    bid: &str,
+    cid: &str,
+    latest: &mut LatestChanges,
+) -> Result<u64> {
     // When we fetch the monitor/changes endpoint manually (ie. not from a push notification)
     // we cannot know the current timestamp, and use 0 abritrarily.
     let expected = 0;
-    let response = get_changeset(&server, "monitor", "changes", expected, None)?;
-    let change = response
-        .changes
-        .iter()
-        .find(|&x| x["bucket"] == bid && x["collection"] == cid)
+    let validators = if latest.validators.is_empty() {
+        None
+    } else {
+        Some(&latest.validators)
+    };
+    match get_changeset(
+        http, server, "monitor", "changes", expected, None, validators,
+    )? {
+        Changeset::Modified(response) => {
+            latest.backoff = response.backoff;
+            latest.validators = response.validators;
+            latest.timestamps = response
+                .changes
+                .into_iter()
+                .map(|change| {
+                    let key = (
+                        change["bucket"].as_str().unwrap_or_default().to_string(),
+                        change["collection"]
+                            .as_str()
+                            .unwrap_or_default()
+                            .to_string(),
+                    );
+                    (key, change["last_modified"].clone())
+                })
+                .collect();
+        }
+        Changeset::NotModified { backoff } => {
+            debug!("monitor/changes was not modified");
+            latest.backoff = backoff;
+        }
+    };
+
+    let last_modified = latest
+        .timestamps
+        .get(&(bid.to_string(), cid.to_string()))
         .ok_or_else(|| KintoError::UnknownCollection {
             bucket: bid.to_string(),
             collection: cid.to_string(),
         })?;
 
-    let last_modified = change["last_modified"].as_u64().ok_or_else(|| {
-        KintoError::InvalidChangesetTimestamp(change["last_modified"].to_string())
-    })?;
+    let last_modified = last_modified
+        .as_u64()
+        .ok_or_else(|| KintoError::InvalidChangesetTimestamp(last_modified.to_string()))?;
 
     debug!("{}/{}: last_modified={}", bid, cid, last_modified);
 
@@ -95,20 +309,289 @@ pub fn get_latest_change_timestamp(server: &str, bid: &str, cid: &str) -> Result
 }
 
 /// Fetches the collection content from the server.
+///
+/// If `validators` of a previous response are specified, the request is conditional
+/// and [`Changeset::NotModified`] is returned when the server content was not modified.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "debug",
+        skip(http, validators),
+        fields(size = tracing::field::Empty, changes = tracing::field::Empty),
+        err
+    )
+)]
 pub fn get_changeset(
+    http: &dyn HttpClient,
     server: &str,
     bid: &str,
     cid: &str,
     expected: u64,
     since: Option<u64>,
-) -> Result<ChangesetResponse> {
+    validators: Option<&CacheValidators>,
+) -> Result<Changeset> {
     let since_param = since.map_or_else(String::new, |v| format!("&_since={}", v));
     let url = format!(
         "{}/buckets/{}/collections/{}/changeset?_expected={}{}",
         server, bid, cid, expected, since_param
     );
     info!("Fetch {}...", url);
-    let response = Request::get(Url::parse(&url)?).send()?;
+    let mut request = Request::get(Url::parse(&url)?);
+    if let Some(validators) = validators {
+        if let Some(ref etag) = validators.etag {
+            request = request.header("If-None-Match", etag.as_str())?;
+        } else if let Some(ref last_modified) = validators.last_modified {
+            request = request.header("If-Modified-Since", last_modified.as_str())?;
+        }
+    }
+    let response = http.send(request)?;
+
+    if response.status == 304 {
+        log_alert(&response);
+        debug!("Not modified since last fetch");
+        return Ok(Changeset::NotModified {
+            backoff: backoff_from_response(&response),
+        });
+    }
+
+    let response = check_response(response)?;
+
+    let size: Option<u64> = response
+        .headers
+        .get("content-length")
+        .and_then(|v| v.parse().ok());
+
+    debug!("Download {:?} bytes...", size);
+    let mut changeset: ChangesetResponse = response.json()?;
+
+    changeset.size = size;
+    if let Some(size) = size {
+        record_span_field!("size", size);
+    }
+    record_span_field!("changes", changeset.changes.len());
+
+    // Check if server is indicating to clients to back-off.
+    changeset.backoff = backoff_from_response(&response);
+
+    changeset.validators = CacheValidators::from_response(&response);
+
+    changeset.alert = Alert::from_response(&response);
+
+    Ok(Changeset::Modified(changeset))
+}
+
+/// Number of seconds to back off, from the `Backoff` header or else the `Retry-After` header.
+fn backoff_from_response(response: &Response) -> Option<u64> {
+    ["backoff", "retry-after"]
+        .iter()
+        .find_map(|name| response.headers.get(*name).and_then(|v| v.parse().ok()))
+}
+
+/// Parameters of the plural endpoints (sorting, pagination, fields selection and filtering).
+///
+/// See <https://docs.kinto-storage.org/en/stable/api/1.x/filtering.html>
+///
+/// # Examples
+/// ```rust
+/// # use remote_settings_client::client::ListParams;
+/// let params = ListParams::new()
+///     .sort("-last_modified")
+///     .limit(100)
+///     .field("name")
+///     .filter("min_last_modified", "1600000000000");
+/// ```
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ListParams {
+    pub sort: Vec<String>,
+    pub limit: Option<u64>,
+    pub fields: Vec<String>,
+    pub filters: Vec<(String, String)>,
+}
+
+impl ListParams {
+    pub fn new() -> Self {
+        ListParams::default()
+    }
+
+    /// Sort by the specified field (prefixed with `-` for descending order).
+    pub fn sort(mut self, field: &str) -> Self {
+        self.sort.push(field.to_string());
+        self
+    }
+
+    /// Set the number of objects per page.
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Only return the specified field (`id` and `last_modified` are always returned).
+    pub fn field(mut self, field: &str) -> Self {
+        self.fields.push(field.to_string());
+        self
+    }
+
+    /// Filter on a field value (eg. `("country", "fr")`, `("min_last_modified", "42")`, `("in_id", "a,b")`).
+    pub fn filter(mut self, name: &str, value: &str) -> Self {
+        self.filters.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    fn apply(&self, url: &mut Url) {
+        let mut query = url.query_pairs_mut();
+        if !self.sort.is_empty() {
+            query.append_pair("_sort", &self.sort.join(","));
+        }
+        if let Some(limit) = self.limit {
+            query.append_pair("_limit", &limit.to_string());
+        }
+        if !self.fields.is_empty() {
+            query.append_pair("_fields", &self.fields.join(","));
+        }
+        for (name, value) in &self.filters {
+            query.append_pair(name, value);
+        }
+    }
+}
+
+/// The objects of a plural endpoint, with all pages fetched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListResponse<T> {
+    pub data: Vec<T>,
+    /// Timestamp of the list (from the `ETag` header).
+    pub timestamp: Option<u64>,
+}
+
+/// Lists the buckets readable on the server.
+pub fn list_buckets(
+    http: &dyn HttpClient,
+    server: &str,
+    params: &ListParams,
+) -> Result<ListResponse<KintoObject>> {
+    get_plural(http, &format!("{}/buckets", server), params)
+}
+
+/// Fetches the attributes of a bucket.
+pub fn get_bucket(http: &dyn HttpClient, server: &str, bid: &str) -> Result<KintoObject> {
+    get_singular(http, &format!("{}/buckets/{}", server, bid))
+}
+
+/// Lists the collections of a bucket.
+pub fn list_collections(
+    http: &dyn HttpClient,
+    server: &str,
+    bid: &str,
+    params: &ListParams,
+) -> Result<ListResponse<KintoObject>> {
+    get_plural(
+        http,
+        &format!("{}/buckets/{}/collections", server, bid),
+        params,
+    )
+}
+
+/// Fetches the attributes (metadata) of a collection.
+pub fn get_collection(
+    http: &dyn HttpClient,
+    server: &str,
+    bid: &str,
+    cid: &str,
+) -> Result<KintoObject> {
+    get_singular(
+        http,
+        &format!("{}/buckets/{}/collections/{}", server, bid, cid),
+    )
+}
+
+/// Lists the records of a collection.
+pub fn list_records(
+    http: &dyn HttpClient,
+    server: &str,
+    bid: &str,
+    cid: &str,
+    params: &ListParams,
+) -> Result<ListResponse<Record>> {
+    get_plural(
+        http,
+        &format!("{}/buckets/{}/collections/{}/records", server, bid, cid),
+        params,
+    )
+}
+
+/// Fetches a single record.
+pub fn get_record(
+    http: &dyn HttpClient,
+    server: &str,
+    bid: &str,
+    cid: &str,
+    rid: &str,
+) -> Result<Record> {
+    get_singular(
+        http,
+        &format!(
+            "{}/buckets/{}/collections/{}/records/{}",
+            server, bid, cid, rid
+        ),
+    )
+}
+
+fn get_singular<T: DeserializeOwned>(http: &dyn HttpClient, url: &str) -> Result<T> {
+    info!("Fetch {}...", url);
+    let response = check_response(http.send(Request::get(Url::parse(url)?))?)?;
+    let body: KintoSingularResponse<T> =
+        response.json().map_err(KintoError::InvalidResponseBody)?;
+    Ok(body.data)
+}
+
+fn get_plural<T: DeserializeOwned>(
+    http: &dyn HttpClient,
+    url: &str,
+    params: &ListParams,
+) -> Result<ListResponse<T>> {
+    let mut url = Url::parse(url)?;
+    params.apply(&mut url);
+
+    let mut data = Vec::new();
+    let mut timestamp = None;
+    let mut next_page = Some(url);
+    while let Some(url) = next_page.take() {
+        info!("Fetch {}...", url);
+        let response = check_response(http.send(Request::get(url))?)?;
+
+        // The list timestamp is the one of the first page.
+        if timestamp.is_none() {
+            timestamp = response
+                .headers
+                .get("etag")
+                .and_then(|v| v.trim_matches('"').parse().ok());
+        }
+        if let Some(next) = response.headers.get("next-page") {
+            next_page = Some(Url::parse(next)?);
+        }
+
+        let page: KintoPluralResponse<T> =
+            response.json().map_err(KintoError::InvalidResponseBody)?;
+        debug!("Received {} objects", page.data.len());
+        data.extend(page.data);
+    }
+
+    Ok(ListResponse { data, timestamp })
+}
+
+fn log_alert(response: &Response) {
+    if let Some(alert) = Alert::from_response(response) {
+        warn!(
+            "Server alert on {} ({}): {}",
+            response.url,
+            alert.code,
+            alert.message.as_deref().unwrap_or_default()
+        );
+    }
+}
+
+/// Turn error responses into the corresponding [`KintoError`].
+pub(crate) fn check_response(response: Response) -> Result<Response> {
+    log_alert(&response);
 
     if !response.is_success() {
         // Try to parse the server error response into JSON.
@@ -118,6 +601,11 @@ pub fn get_changeset(
             Err(_) => return Err(KintoError::UnexpectedResponse { response }),
         };
 
+        // The object was modified meanwhile (`If-Match`) or already exists (`If-None-Match`).
+        if response.status == 412 {
+            return Err(KintoError::PreconditionFailed { response, info });
+        }
+
         // Error due to the client. The request must be modified.
         if response.is_client_error() {
             return Err(KintoError::ClientRequestError { response, info });
@@ -137,23 +625,16 @@ pub fn get_changeset(
         }
     }
 
-    let size: i64 = response
-        .headers
-        .get("content-length")
-        .map_or_else(|| -1, |v| v.parse().unwrap_or(-1));
-
-    debug!("Download {:?} bytes...", size);
-    let mut changeset: ChangesetResponse = response.json()?;
-
-    // Check if server is indicating to clients to back-off.
-    changeset.backoff = response.headers.get("backoff").and_then(|v| v.parse().ok());
-
-    Ok(changeset)
+    Ok(response)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{get_changeset, get_latest_change_timestamp, KintoError};
+    use super::{
+        get_changeset, get_collection, get_latest_change_timestamp, get_server_info, list_records,
+        CacheValidators, Changeset, KintoError, LatestChanges, ListParams,
+    };
+    use crate::client::http::ViaductHttpClient;
     use httpmock::MockServer;
     use viaduct::set_backend;
     use viaduct_reqwest::ReqwestBackend;
@@ -189,9 +670,14 @@ mod tests {
             );
         });
 
-        let res =
-            get_latest_change_timestamp(&mock_server_address, "main", "url-classifier-skip-urls")
-                .unwrap();
+        let res = get_latest_change_timestamp(
+            &ViaductHttpClient {},
+            &mock_server_address,
+            "main",
+            "url-classifier-skip-urls",
+            &mut LatestChanges::default(),
+        )
+        .unwrap();
 
         assert_eq!(res, 9173);
 
@@ -202,8 +688,14 @@ mod tests {
     fn test_bad_url() {
         init();
 
-        let err =
-            get_latest_change_timestamp("%^", "main", "url-classifier-skip-urls").unwrap_err();
+        let err = get_latest_change_timestamp(
+            &ViaductHttpClient {},
+            "%^",
+            "main",
+            "url-classifier-skip-urls",
+            &mut LatestChanges::default(),
+        )
+        .unwrap_err();
         assert_eq!(
             err.to_string(),
             "bad URL format: relative URL without a base"
@@ -226,9 +718,14 @@ mod tests {
             );
         });
 
-        let err =
-            get_latest_change_timestamp(&mock_server_address, "main", "url-classifier-skip-urls")
-                .unwrap_err();
+        let err = get_latest_change_timestamp(
+            &ViaductHttpClient {},
+            &mock_server_address,
+            "main",
+            "url-classifier-skip-urls",
+            &mut LatestChanges::default(),
+        )
+        .unwrap_err();
         assert_eq!(err.to_string(), "changeset content could not be parsed: control character (\\u0000-\\u001F) found while parsing a string at line 3 column 0");
 
         get_latest_change_mock.delete();
@@ -260,9 +757,14 @@ mod tests {
             );
         });
 
-        let err =
-            get_latest_change_timestamp(&mock_server_address, "main", "url-classifier-skip-urls")
-                .unwrap_err();
+        let err = get_latest_change_timestamp(
+            &ViaductHttpClient {},
+            &mock_server_address,
+            "main",
+            "url-classifier-skip-urls",
+            &mut LatestChanges::default(),
+        )
+        .unwrap_err();
 
         match err {
             KintoError::InvalidChangesetTimestamp(_) => {
@@ -301,7 +803,16 @@ mod tests {
             );
         });
 
-        let err = get_changeset(&mock_server_address, "main", "cfr", 451, None).unwrap_err();
+        let err = get_changeset(
+            &ViaductHttpClient {},
+            &mock_server_address,
+            "main",
+            "cfr",
+            451,
+            None,
+            None,
+        )
+        .unwrap_err();
 
         match err {
             KintoError::ClientRequestError { ref info, .. } => {
@@ -338,7 +849,16 @@ mod tests {
             );
         });
 
-        let err = get_changeset(&mock_server_address, "main", "cfr", 42, None).unwrap_err();
+        let err = get_changeset(
+            &ViaductHttpClient {},
+            &mock_server_address,
+            "main",
+            "cfr",
+            42,
+            None,
+            None,
+        )
+        .unwrap_err();
 
         match err {
             KintoError::ServerError {
@@ -391,7 +911,14 @@ mod tests {
             );
         });
 
-        let res = get_latest_change_timestamp(&mock_server.url(""), "main", "crlite").unwrap();
+        let res = get_latest_change_timestamp(
+            &ViaductHttpClient {},
+            &mock_server.url(""),
+            "main",
+            "crlite",
+            &mut LatestChanges::default(),
+        )
+        .unwrap();
 
         assert_eq!(res, 5678);
 
@@ -401,4 +928,314 @@ mod tests {
         redirects_mock.delete();
         changeset_mock.delete();
     }
+
+    #[test]
+    fn test_latest_change_uses_etag() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mock_server_address = mock_server.url("");
+
+        let mut not_modified_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/monitor/collections/changes/changeset")
+                .header("If-None-Match", "\"42\"");
+            then.status(304).header("Retry-After", "120");
+        });
+
+        let mut changes_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/monitor/collections/changes/changeset");
+            then.header("ETag", "\"42\"").body(
+                r#"{
+                    "metadata": {},
+                    "changes": [
+                        {
+                            "id": "123",
+                            "last_modified": 42,
+                            "bucket":"main",
+                            "collection":"cfr"
+                        }
+                    ],
+                    "timestamp": 42
+                }"#,
+            );
+        });
+
+        let mut latest = LatestChanges::default();
+
+        let first = get_latest_change_timestamp(
+            &ViaductHttpClient {},
+            &mock_server_address,
+            "main",
+            "cfr",
+            &mut latest,
+        )
+        .unwrap();
+        assert_eq!(latest.validators.etag.as_deref(), Some("\"42\""));
+        assert_eq!(latest.backoff(), None);
+
+        let second = get_latest_change_timestamp(
+            &ViaductHttpClient {},
+            &mock_server_address,
+            "main",
+            "cfr",
+            &mut latest,
+        )
+        .unwrap();
+
+        assert_eq!(first, 42);
+        assert_eq!(second, 42);
+        assert_eq!(latest.backoff(), Some(120));
+        changes_mock.assert_hits(1);
+        not_modified_mock.assert_hits(1);
+
+        changes_mock.delete();
+        not_modified_mock.delete();
+    }
+
+    #[test]
+    fn test_changeset_not_modified() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mock_server_address = mock_server.url("");
+
+        let mut get_changeset_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/cfr/changeset")
+                .header("If-None-Match", "\"13\"");
+            then.status(304).header("Backoff", "60");
+        });
+
+        let validators = CacheValidators {
+            etag: Some("\"13\"".to_string()),
+            last_modified: None,
+        };
+        let res = get_changeset(
+            &ViaductHttpClient {},
+            &mock_server_address,
+            "main",
+            "cfr",
+            13,
+            Some(10),
+            Some(&validators),
+        )
+        .unwrap();
+
+        assert!(matches!(res, Changeset::NotModified { backoff: Some(60) }));
+
+        get_changeset_mock.assert();
+        get_changeset_mock.delete();
+    }
+
+    #[test]
+    fn test_changeset_alert() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mut alert_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/cfr/changeset");
+            then.header(
+                "Alert",
+                r#"{"code": "hard-eol", "message": "Service was decommissioned", "url": null}"#,
+            )
+            .body(r#"{"metadata": {}, "changes": [], "timestamp": 42}"#);
+        });
+        let mut malformed_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/tippytop/changeset");
+            then.header("Alert", "not-json")
+                .body(r#"{"metadata": {}, "changes": [], "timestamp": 42}"#);
+        });
+
+        let http = ViaductHttpClient {};
+        let changeset =
+            match get_changeset(&http, &mock_server.url(""), "main", "cfr", 42, None, None) {
+                Ok(Changeset::Modified(changeset)) => changeset,
+                other => panic!("unexpected result: {:?}", other),
+            };
+        let alert = changeset.alert.unwrap();
+        assert_eq!(alert.code, "hard-eol");
+        assert_eq!(alert.message.as_deref(), Some("Service was decommissioned"));
+        assert_eq!(alert.url, None);
+
+        let changeset = match get_changeset(
+            &http,
+            &mock_server.url(""),
+            "main",
+            "tippytop",
+            42,
+            None,
+            None,
+        ) {
+            Ok(Changeset::Modified(changeset)) => changeset,
+            other => panic!("unexpected result: {:?}", other),
+        };
+        assert_eq!(changeset.alert, None);
+
+        alert_mock.assert();
+        malformed_mock.assert();
+        alert_mock.delete();
+        malformed_mock.delete();
+    }
+
+    #[test]
+    fn test_server_info() {
+        init();
+
+        let mock_server = MockServer::start();
+
+        let mut server_info_mock = mock_server.mock(|when, then| {
+            when.path("/v1/");
+            then.body(
+                r#"{
+                    "project_name": "Remote Settings PROD",
+                    "project_version": "29.1.0",
+                    "http_api_version": "1.22",
+                    "project_docs": "https://remote-settings.readthedocs.io",
+                    "url": "https://firefox.settings.services.mozilla.com/v1/",
+                    "settings": {
+                        "readonly": true
+                    },
+                    "capabilities": {
+                        "changes": {
+                            "description": "Track modifications of records in Kinto.",
+                            "collections": ["/buckets/main"]
+                        },
+                        "attachments": {
+                            "base_url": "https://firefox-settings-attachments.cdn.mozilla.net/"
+                        },
+                        "signer": {
+                            "to_review_enabled": true,
+                            "resources": []
+                        },
+                        "history": {}
+                    }
+                }"#,
+            );
+        });
+
+        let info = get_server_info(&ViaductHttpClient {}, &mock_server.url("/v1")).unwrap();
+
+        assert_eq!(info.project_name, "Remote Settings PROD");
+        assert_eq!(info.project_version, "29.1.0");
+        assert_eq!(info.eos, None);
+        assert_eq!(
+            info.attachments_base_url(),
+            Some("https://firefox-settings-attachments.cdn.mozilla.net/")
+        );
+        assert_eq!(
+            info.capabilities.changes.as_ref().unwrap().collections,
+            vec!["/buckets/main"]
+        );
+        assert!(info.capabilities.signer.as_ref().unwrap().to_review_enabled);
+        assert!(info.has_capability("history"));
+        assert!(!info.has_capability("admin"));
+
+        server_info_mock.assert();
+        server_info_mock.delete();
+    }
+
+    #[test]
+    fn test_server_info_bad_content() {
+        init();
+
+        let mock_server = MockServer::start();
+
+        let mut server_info_mock = mock_server.mock(|when, then| {
+            when.path("/v1/");
+            then.body(r#"{"project_name": "Kinto"}"#);
+        });
+
+        let err = get_server_info(&ViaductHttpClient {}, &mock_server.url("/v1")).unwrap_err();
+
+        assert!(matches!(err, KintoError::InvalidServerInfo(_)));
+
+        server_info_mock.assert();
+        server_info_mock.delete();
+    }
+
+    #[test]
+    fn test_list_records_follows_pages() {
+        init();
+
+        let mock_server = MockServer::start();
+        let next_page =
+            mock_server.url("/buckets/main/collections/cfr/records?_limit=1&_token=abc");
+
+        let mut page_2_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/cfr/records")
+                .query_param("_token", "abc");
+            then.body(r#"{"data": [{"id": "b", "last_modified": 41}]}"#);
+        });
+
+        let mut page_1_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/cfr/records")
+                .query_param("_sort", "-last_modified,id")
+                .query_param("_limit", "1")
+                .query_param("_fields", "name")
+                .query_param("has_name", "true");
+            then.header("ETag", "\"42\"")
+                .header("Next-Page", &next_page)
+                .body(r#"{"data": [{"id": "a", "last_modified": 42, "name": "A"}]}"#);
+        });
+
+        let params = ListParams::new()
+            .sort("-last_modified")
+            .sort("id")
+            .limit(1)
+            .field("name")
+            .filter("has_name", "true");
+        let res = list_records(
+            &ViaductHttpClient {},
+            &mock_server.url(""),
+            "main",
+            "cfr",
+            &params,
+        )
+        .unwrap();
+
+        assert_eq!(res.timestamp, Some(42));
+        assert_eq!(res.data.len(), 2);
+        assert_eq!(res.data[0].id(), "a");
+        assert_eq!(res.data[0]["name"].as_str(), Some("A"));
+        assert_eq!(res.data[1].id(), "b");
+
+        page_1_mock.assert();
+        page_2_mock.assert();
+        page_1_mock.delete();
+        page_2_mock.delete();
+    }
+
+    #[test]
+    fn test_get_collection_not_found() {
+        init();
+
+        let mock_server = MockServer::start();
+
+        let mut collection_mock = mock_server.mock(|when, then| {
+            when.path("/buckets/main/collections/unknown");
+            then.status(404).body(
+                r#"{
+                    "code": 404,
+                    "errno": 111,
+                    "error": "Not Found",
+                    "message": "collection not found"
+                }"#,
+            );
+        });
+
+        let err = get_collection(
+            &ViaductHttpClient {},
+            &mock_server.url(""),
+            "main",
+            "unknown",
+        )
+        .unwrap_err();
+
+        match err {
+            KintoError::ClientRequestError { info, .. } => assert_eq!(info.errno, 111),
+            e => assert!(false, "Unexpected error type: {:?}", e),
+        };
+
+        collection_mock.assert();
+        collection_mock.delete();
+    }
 }
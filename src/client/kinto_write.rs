@@ -0,0 +1,415 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use super::http::{HttpClient, ViaductHttpClient};
+use super::kinto_http::{check_response, KintoError, KintoObject, KintoSingularResponse};
+use super::Record;
+use log::info;
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use url::Url;
+use viaduct::{Error as ViaductError, Request, Response};
+
+type Result<T> = std::result::Result<T, KintoError>;
+
+/// Credentials sent in the `Authorization` header of write requests.
+#[derive(Clone)]
+pub enum Authentication {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+impl Authentication {
+    fn header_value(&self) -> String {
+        match self {
+            Authentication::Basic { username, password } => format!(
+                "Basic {}",
+                base64::encode(format!("{}:{}", username, password))
+            ),
+            Authentication::Bearer(token) => format!("Bearer {}", token),
+        }
+    }
+}
+
+impl std::fmt::Debug for Authentication {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never show credentials in logs.
+        match self {
+            Authentication::Basic { username, .. } => write!(f, "Basic({}:***)", username),
+            Authentication::Bearer(_) => write!(f, "Bearer(***)"),
+        }
+    }
+}
+
+/// Concurrency control of write requests.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Precondition {
+    /// Write unconditionally.
+    None,
+    /// Only write if the object was not modified since this timestamp (`If-Match`).
+    IfMatch(u64),
+    /// Only write if the object does not exist yet (`If-None-Match: *`).
+    IfNoneMatch,
+}
+
+/// Client to write records and collections on a Kinto server.
+///
+/// With the `write_api` feature.
+///
+/// # Examples
+/// ```rust
+/// # use remote_settings_client::client::kinto_write::{AuthenticatedClient, Authentication};
+/// # fn main() {
+/// let client = AuthenticatedClient::builder()
+///   .server_url("https://settings-writer.stage.mozaws.net/v1")
+///   .authentication(Authentication::Bearer("token".to_owned()))
+///   .build()
+///   .unwrap();
+/// # }
+/// ```
+///
+/// When the object was modified meanwhile, or already exists, a [`KintoError::PreconditionFailed`]
+/// is returned.
+#[derive(Builder, Debug)]
+#[builder(pattern = "owned")] // No clone because of Box<dyn...>
+pub struct AuthenticatedClient {
+    #[builder(setter(into))]
+    server_url: String,
+    authentication: Authentication,
+    #[builder(default = "Box::new(ViaductHttpClient {})")]
+    http_client: Box<dyn HttpClient>,
+}
+
+impl AuthenticatedClient {
+    /// Creates a `AuthenticatedClientBuilder` to configure a `AuthenticatedClient`.
+    pub fn builder() -> AuthenticatedClientBuilder {
+        AuthenticatedClientBuilder::default()
+    }
+
+    /// Create a record. If no `id` is specified in `data`, the server assigns one.
+    ///
+    /// # Errors
+    /// If a record with the same `id` already exists, a [`KintoError::PreconditionFailed`] is returned.
+    pub fn create_record(&self, bid: &str, cid: &str, data: &KintoObject) -> Result<Record> {
+        match data["id"].as_str() {
+            Some(rid) => self.put(
+                &self.record_url(bid, cid, rid),
+                data,
+                Precondition::IfNoneMatch,
+            ),
+            None => {
+                let url = format!(
+                    "{}/buckets/{}/collections/{}/records",
+                    self.server_url, bid, cid
+                );
+                self.request(
+                    Request::post(Url::parse(&url)?),
+                    Some(data),
+                    Precondition::None,
+                )
+            }
+        }
+    }
+
+    /// Replace the content of a record (or create it if missing).
+    pub fn update_record(
+        &self,
+        bid: &str,
+        cid: &str,
+        rid: &str,
+        data: &KintoObject,
+        precondition: Precondition,
+    ) -> Result<Record> {
+        self.put(&self.record_url(bid, cid, rid), data, precondition)
+    }
+
+    /// Delete a record, and return its tombstone.
+    pub fn delete_record(
+        &self,
+        bid: &str,
+        cid: &str,
+        rid: &str,
+        precondition: Precondition,
+    ) -> Result<Record> {
+        let url = self.record_url(bid, cid, rid);
+        self.request(Request::delete(Url::parse(&url)?), None, precondition)
+    }
+
+    /// Create a collection, with the specified attributes (metadata).
+    ///
+    /// # Errors
+    /// If the collection already exists, a [`KintoError::PreconditionFailed`] is returned.
+    pub fn create_collection(
+        &self,
+        bid: &str,
+        cid: &str,
+        data: &KintoObject,
+    ) -> Result<KintoObject> {
+        self.put(
+            &self.collection_url(bid, cid),
+            data,
+            Precondition::IfNoneMatch,
+        )
+    }
+
+    /// Merge the specified attributes into the collection metadata.
+    pub fn patch_collection(
+        &self,
+        bid: &str,
+        cid: &str,
+        data: &KintoObject,
+        precondition: Precondition,
+    ) -> Result<KintoObject> {
+        let url = self.collection_url(bid, cid);
+        self.request(Request::patch(Url::parse(&url)?), Some(data), precondition)
+    }
+
+    /// Delete a collection and all its records.
+    pub fn delete_collection(
+        &self,
+        bid: &str,
+        cid: &str,
+        precondition: Precondition,
+    ) -> Result<KintoObject> {
+        let url = self.collection_url(bid, cid);
+        self.request(Request::delete(Url::parse(&url)?), None, precondition)
+    }
+
+    pub(crate) fn server_url(&self) -> &str {
+        &self.server_url
+    }
+
+    fn collection_url(&self, bid: &str, cid: &str) -> String {
+        format!("{}/buckets/{}/collections/{}", self.server_url, bid, cid)
+    }
+
+    fn record_url(&self, bid: &str, cid: &str, rid: &str) -> String {
+        format!("{}/records/{}", self.collection_url(bid, cid), rid)
+    }
+
+    fn put<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        data: &KintoObject,
+        precondition: Precondition,
+    ) -> Result<T> {
+        self.request(Request::put(Url::parse(url)?), Some(data), precondition)
+    }
+
+    fn request<T: DeserializeOwned>(
+        &self,
+        request: Request,
+        data: Option<&KintoObject>,
+        precondition: Precondition,
+    ) -> Result<T> {
+        info!("{} {}...", request.method, request.url);
+        let mut request = match precondition {
+            Precondition::None => request,
+            Precondition::IfMatch(timestamp) => {
+                request.header("If-Match", format!("\"{}\"", timestamp))?
+            }
+            Precondition::IfNoneMatch => request.header("If-None-Match", "*")?,
+        };
+        if let Some(data) = data {
+            request = request.json(&json!({ "data": data }));
+        }
+
+        let response = check_response(self.send(request)?)?;
+
+        let body: KintoSingularResponse<T> =
+            response.json().map_err(KintoError::InvalidResponseBody)?;
+        Ok(body.data)
+    }
+}
+
+/// The authenticated client can be used with the read functions of [`kinto_http`](super::kinto_http),
+/// in order to read private buckets and collections.
+impl HttpClient for AuthenticatedClient {
+    fn send(&self, request: Request) -> std::result::Result<Response, ViaductError> {
+        let request = request.header("Authorization", self.authentication.header_value())?;
+        self.http_client.send(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuthenticatedClient, Authentication, Precondition};
+    use crate::client::kinto_http::KintoError;
+    use httpmock::Method::{DELETE, PATCH, POST, PUT};
+    use httpmock::MockServer;
+    use serde_json::json;
+    use viaduct::set_backend;
+    use viaduct_reqwest::ReqwestBackend;
+
+    fn init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let _ = set_backend(&ReqwestBackend);
+    }
+
+    fn client(mock_server: &MockServer) -> AuthenticatedClient {
+        AuthenticatedClient::builder()
+            .server_url(mock_server.url(""))
+            .authentication(Authentication::Basic {
+                username: "user".to_owned(),
+                password: "pass".to_owned(),
+            })
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_credentials_are_hidden() {
+        let auth = Authentication::Basic {
+            username: "user".to_owned(),
+            password: "pass".to_owned(),
+        };
+        assert_eq!(format!("{:?}", auth), "Basic(user:***)");
+        assert_eq!(
+            format!("{:?}", Authentication::Bearer("token".to_owned())),
+            "Bearer(***)"
+        );
+    }
+
+    #[test]
+    fn test_create_record_without_id() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mut create_mock = mock_server.mock(|when, then| {
+            when.method(POST)
+                .path("/buckets/main-workspace/collections/cfr/records")
+                .header("Authorization", "Basic dXNlcjpwYXNz")
+                .json_body(json!({"data": {"foo": "bar"}}));
+            then.status(201)
+                .body(r#"{"data": {"id": "abc", "last_modified": 42, "foo": "bar"}}"#);
+        });
+
+        let record = client(&mock_server)
+            .create_record("main-workspace", "cfr", &json!({"foo": "bar"}))
+            .unwrap();
+
+        assert_eq!(record.id(), "abc");
+        assert_eq!(record.last_modified(), 42);
+
+        create_mock.assert();
+        create_mock.delete();
+    }
+
+    #[test]
+    fn test_create_record_already_exists() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mut create_mock = mock_server.mock(|when, then| {
+            when.method(PUT)
+                .path("/buckets/main-workspace/collections/cfr/records/abc")
+                .header("If-None-Match", "*");
+            then.status(412).body(
+                r#"{
+                    "code": 412,
+                    "errno": 114,
+                    "error": "Precondition Failed",
+                    "message": "Resource was modified meanwhile",
+                    "details": {"existing": {"id": "abc", "last_modified": 41}}
+                }"#,
+            );
+        });
+
+        let err = client(&mock_server)
+            .create_record("main-workspace", "cfr", &json!({"id": "abc"}))
+            .unwrap_err();
+
+        match err {
+            KintoError::PreconditionFailed { info, .. } => {
+                assert_eq!(info.errno, 114);
+                assert_eq!(info.details.unwrap()["existing"]["last_modified"], 41);
+            }
+            e => assert!(false, "Unexpected error type: {:?}", e),
+        };
+
+        create_mock.assert();
+        create_mock.delete();
+    }
+
+    #[test]
+    fn test_update_and_delete_record_if_match() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mut update_mock = mock_server.mock(|when, then| {
+            when.method(PUT)
+                .path("/buckets/main-workspace/collections/cfr/records/abc")
+                .header("If-Match", "\"41\"")
+                .json_body(json!({"data": {"foo": "baz"}}));
+            then.body(r#"{"data": {"id": "abc", "last_modified": 42, "foo": "baz"}}"#);
+        });
+        let mut delete_mock = mock_server.mock(|when, then| {
+            when.method(DELETE)
+                .path("/buckets/main-workspace/collections/cfr/records/abc")
+                .header("If-Match", "\"42\"");
+            then.body(r#"{"data": {"id": "abc", "last_modified": 43, "deleted": true}}"#);
+        });
+
+        let client = client(&mock_server);
+        let updated = client
+            .update_record(
+                "main-workspace",
+                "cfr",
+                "abc",
+                &json!({"foo": "baz"}),
+                Precondition::IfMatch(41),
+            )
+            .unwrap();
+        let deleted = client
+            .delete_record(
+                "main-workspace",
+                "cfr",
+                "abc",
+                Precondition::IfMatch(updated.last_modified()),
+            )
+            .unwrap();
+
+        assert_eq!(updated["foo"].as_str(), Some("baz"));
+        assert!(deleted.deleted());
+
+        update_mock.assert();
+        delete_mock.assert();
+        update_mock.delete();
+        delete_mock.delete();
+    }
+
+    #[test]
+    fn test_patch_collection_with_bearer_token() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mut patch_mock = mock_server.mock(|when, then| {
+            when.method(PATCH)
+                .path("/buckets/main-workspace/collections/cfr")
+                .header("Authorization", "Bearer s3cr3t")
+                .json_body(json!({"data": {"status": "to-review"}}));
+            then.body(r#"{"data": {"id": "cfr", "last_modified": 42, "status": "to-review"}}"#);
+        });
+
+        let client = AuthenticatedClient::builder()
+            .server_url(mock_server.url(""))
+            .authentication(Authentication::Bearer("s3cr3t".to_owned()))
+            .build()
+            .unwrap();
+
+        let metadata = client
+            .patch_collection(
+                "main-workspace",
+                "cfr",
+                &json!({"status": "to-review"}),
+                Precondition::None,
+            )
+            .unwrap();
+
+        assert_eq!(metadata["status"], "to-review");
+
+        patch_mock.assert();
+        patch_mock.delete();
+    }
+}
@@ -0,0 +1,77 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use super::signatures::SignatureError;
+use super::ClientError;
+use std::time::Duration;
+
+/// A trait for reporting metrics about synchronizations and signature verifications.
+///
+/// Every method has a no-op default implementation, only the relevant ones have to be implemented.
+/// Each call receives the bucket and collection names of the client.
+///
+/// # How can I implement ```Metrics```?
+/// ```rust
+/// # use remote_settings_client::{Client, Metrics};
+/// # use remote_settings_client::client::ClientError;
+/// # use std::time::Duration;
+/// struct PrintMetrics {}
+///
+/// impl Metrics for PrintMetrics {
+///     fn sync_finished(
+///         &self,
+///         bid: &str,
+///         cid: &str,
+///         duration: Duration,
+///         result: Result<(), &ClientError>,
+///     ) {
+///         println!("{}/{} synced in {:?}: {}", bid, cid, duration, result.is_ok());
+///     }
+/// }
+///
+/// # fn main() {
+/// let client = Client::builder()
+///    .collection_name("cid")
+///    .metrics(Box::new(PrintMetrics {}))
+///    .build();
+/// # }
+/// ```
+pub trait Metrics: Send {
+    /// A synchronization was started.
+    fn sync_started(&self, _bid: &str, _cid: &str) {}
+
+    /// A synchronization has finished, successfully or not.
+    fn sync_finished(
+        &self,
+        _bid: &str,
+        _cid: &str,
+        _duration: Duration,
+        _result: Result<(), &ClientError>,
+    ) {
+    }
+
+    /// A changeset was downloaded. The `size` in bytes comes from the `Content-Length` header.
+    fn changeset_downloaded(&self, _bid: &str, _cid: &str, _size: Option<u64>) {}
+
+    /// Changes of a changeset were merged into the local records.
+    fn changes_applied(&self, _bid: &str, _cid: &str, _count: usize) {}
+
+    /// The signature of the collection was verified.
+    fn verification_finished(
+        &self,
+        _bid: &str,
+        _cid: &str,
+        _duration: Duration,
+        _result: Result<(), &SignatureError>,
+    ) {
+    }
+
+    /// The server indicated the client to backoff for the specified duration.
+    fn backoff_entered(&self, _bid: &str, _cid: &str, _duration: Duration) {}
+}
+
+/// Default `Metrics`, which does not report anything.
+pub struct NoopMetrics {}
+
+impl Metrics for NoopMetrics {}
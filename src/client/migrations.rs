@@ -0,0 +1,114 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use super::storage::StorageError;
+use log::debug;
+use serde_json::Value;
+
+/// Version of the schema of stored collections.
+///
+/// It must be increased whenever the serialized form of [`super::Collection`] changes,
+/// along with a migration from the previous version in [`MIGRATIONS`].
+pub(crate) const SCHEMA_VERSION: u8 = 1;
+
+/// Upgrade a stored collection to the next schema version.
+pub(crate) type Migration = fn(Value) -> Result<Value, StorageError>;
+
+/// Registry of migrations, by the schema version they upgrade from.
+///
+/// Collections stored with a version that cannot be upgraded are synced again from the server.
+pub(crate) const MIGRATIONS: &[(u8, Migration)] = &[];
+
+/// Upgrade a stored collection from the schema version `from` to `to`, by applying
+/// the registered migrations in sequence.
+///
+/// # Errors
+/// If a migration is missing or fails, a [`StorageError::UnsupportedSchema`] is returned.
+pub(crate) fn migrate(
+    mut value: Value,
+    from: u8,
+    to: u8,
+    migrations: &[(u8, Migration)],
+) -> Result<Value, StorageError> {
+    if from > to {
+        return Err(StorageError::UnsupportedSchema(format!(
+            "collection stored with a newer schema version ({} > {})",
+            from, to
+        )));
+    }
+    for version in from..to {
+        let migration = migrations
+            .iter()
+            .find(|(v, _)| *v == version)
+            .map(|(_, migration)| migration)
+            .ok_or_else(|| {
+                StorageError::UnsupportedSchema(format!(
+                    "no migration from schema version {}",
+                    version
+                ))
+            })?;
+        debug!("Migrate stored collection from schema version {}", version);
+        value = migration(value).map_err(|err| {
+            StorageError::UnsupportedSchema(format!(
+                "migration from schema version {} failed: {}",
+                version, err
+            ))
+        })?;
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{migrate, Migration};
+    use crate::client::StorageError;
+    use serde_json::{json, Value};
+
+    fn rename_data(mut value: Value) -> Result<Value, StorageError> {
+        let records = value["data"].take();
+        value["records"] = records;
+        Ok(value)
+    }
+
+    fn add_signer(mut value: Value) -> Result<Value, StorageError> {
+        value["signer"] = json!("signer");
+        Ok(value)
+    }
+
+    const MIGRATIONS: &[(u8, Migration)] = &[(1, add_signer), (0, rename_data)];
+
+    #[test]
+    fn test_migrate_in_sequence() {
+        let migrated = migrate(json!({"data": []}), 0, 2, MIGRATIONS).unwrap();
+
+        assert_eq!(
+            migrated,
+            json!({"records": [], "data": null, "signer": "signer"})
+        );
+        assert_eq!(migrate(json!({}), 2, 2, MIGRATIONS).unwrap(), json!({}));
+    }
+
+    #[test]
+    fn test_migrate_unsupported_versions() {
+        assert_eq!(
+            migrate(json!({}), 0, 4, MIGRATIONS).unwrap_err(),
+            StorageError::UnsupportedSchema("no migration from schema version 2".to_string())
+        );
+        assert!(matches!(
+            migrate(json!({}), 3, 2, MIGRATIONS),
+            Err(StorageError::UnsupportedSchema(_))
+        ));
+
+        fn failing(_: Value) -> Result<Value, StorageError> {
+            Err(StorageError::ReadError("missing field".to_string()))
+        }
+        assert_eq!(
+            migrate(json!({}), 0, 1, &[(0, failing)]).unwrap_err(),
+            StorageError::UnsupportedSchema(
+                "migration from schema version 0 failed: cannot read from storage: missing field"
+                    .to_string()
+            )
+        );
+    }
+}
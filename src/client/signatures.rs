@@ -12,6 +12,7 @@ pub mod rc_crypto_verifier;
 
 pub mod x509;
 
+use crate::client::http::HttpClient;
 use crate::client::Collection;
 use hex;
 use log::debug;
@@ -24,7 +25,7 @@ use viaduct::{Error as ViaductError, Request, Response};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 #[cfg(not(test))]
-fn epoch_seconds() -> u64 {
+pub(crate) fn epoch_seconds() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap() // Time won't go backwards.
@@ -35,7 +36,7 @@ fn epoch_seconds() -> u64 {
 use mock_instant;
 
 #[cfg(test)]
-fn epoch_seconds() -> u64 {
+pub(crate) fn epoch_seconds() -> u64 {
     mock_instant::MockClock::time().as_secs()
 }
 
@@ -71,14 +72,18 @@ fn epoch_seconds() -> u64 {
 /// # }
 /// ```
 pub trait Verification: Send {
-    fn fetch_certificate_chain(&self, collection: &Collection) -> Result<Vec<u8>, SignatureError> {
+    fn fetch_certificate_chain(
+        &self,
+        http: &dyn HttpClient,
+        collection: &Collection,
+    ) -> Result<Vec<u8>, SignatureError> {
         // Get public key from collection metadata (PEM URL is `x5u` field).
         let x5u = collection.metadata["signature"]["x5u"]
             .as_str()
             .ok_or(SignatureError::MissingSignatureField())?;
         // Fetch certificate from URL (certificate chain).
         debug!("Fetching certificate {}", x5u);
-        let response = Request::get(Url::parse(&x5u)?).send()?;
+        let response = http.send(Request::get(Url::parse(x5u)?))?;
         if !response.is_success() {
             return Err(SignatureError::CertificateDownloadError { response });
         }
@@ -106,7 +111,7 @@ pub trait Verification: Send {
     }
 
     /// Verifies signature for a given ```Collection``` struct.
-    /// 1. Fetch and parse the chain of PEM-format certificates linked to in the "x5u" property.
+    /// 1. Fetch (using the specified HTTP client) and parse the chain of PEM-format certificates linked to in the "x5u" property.
     /// 2. Serialize the collection data in canonical JSON format.
     /// 3. Verify the certificates chain of trust using root_hash and signer name, and that the ECDSA P384 SHA384 signature matches the data.
     /// # Errors
@@ -115,8 +120,42 @@ pub trait Verification: Send {
     ///
     /// If errors occur during certificate download, parsing, or data serialization, then
     /// the corresponding error is returned.
-    fn verify(&self, collection: &Collection, root_hash: &str) -> Result<(), SignatureError> {
-        let pem_bytes = self.fetch_certificate_chain(&collection)?;
+    fn verify(
+        &self,
+        http: &dyn HttpClient,
+        collection: &Collection,
+        root_hash: &str,
+    ) -> Result<(), SignatureError> {
+        let pem_bytes = self.fetch_certificate_chain(http, collection)?;
+        self.verify_with_certificates(&pem_bytes, collection, root_hash)
+    }
+
+    /// Verifies signature for a given ```Collection``` struct, and returns the expiry
+    /// (in epoch seconds) of its certificates chain, until which the result can be reused.
+    ///
+    /// By default, the expiry is unknown (`None`) and the collection is verified every time.
+    ///
+    /// # Errors
+    /// See [`Verification::verify`].
+    fn verify_with_expiry(
+        &self,
+        http: &dyn HttpClient,
+        collection: &Collection,
+        root_hash: &str,
+    ) -> Result<Option<u64>, SignatureError> {
+        self.verify(http, collection, root_hash).map(|_| None)
+    }
+
+    /// Verifies signature for a given ```Collection``` struct, with the specified chain of PEM-format certificates.
+    ///
+    /// # Errors
+    /// See [`Verification::verify`].
+    fn verify_with_certificates(
+        &self,
+        pem_bytes: &[u8],
+        collection: &Collection,
+        root_hash: &str,
+    ) -> Result<(), SignatureError> {
         let signature_bytes = self.decode_signature(&collection)?;
         let data_bytes = self.serialize_data(&collection)?;
 
@@ -126,7 +165,7 @@ pub trait Verification: Send {
         let now = epoch_seconds();
         self.verify_nist384p_chain(
             now,
-            &pem_bytes,
+            pem_bytes,
             &root_hash_bytes,
             &collection.signer,
             &data_bytes,
@@ -152,6 +191,32 @@ pub trait Verification: Send {
     ) -> Result<(), SignatureError>;
 }
 
+/// Fetch the certificates chain of the collection, verify its signature, and return
+/// the expiry of the chain (ie. of its first certificate to expire).
+#[cfg(any(feature = "ring_verifier", feature = "rc_crypto_verifier"))]
+pub(crate) fn verify_until_expiry<V: Verification + ?Sized>(
+    verifier: &V,
+    http: &dyn HttpClient,
+    collection: &Collection,
+    root_hash: &str,
+) -> Result<Option<u64>, SignatureError> {
+    let pem_bytes = verifier.fetch_certificate_chain(http, collection)?;
+    verifier.verify_with_certificates(&pem_bytes, collection, root_hash)?;
+    Ok(Some(certificate_chain_expiry(&pem_bytes)?))
+}
+
+/// Return the earliest expiry (in epoch seconds) of the certificates of the chain.
+#[cfg(any(feature = "ring_verifier", feature = "rc_crypto_verifier", test))]
+pub(crate) fn certificate_chain_expiry(pem_bytes: &[u8]) -> Result<u64, SignatureError> {
+    let mut expiry = u64::MAX;
+    for pem in x509::parse_certificate_chain(pem_bytes)? {
+        let cert = x509::parse_x509_certificate(&pem)?;
+        let not_after = cert.tbs_certificate.validity.not_after.timestamp();
+        expiry = expiry.min(not_after.max(0) as u64);
+    }
+    Ok(expiry)
+}
+
 #[derive(Debug, Error)]
 pub enum SignatureError {
     #[error("signature mismatch: {0}")]
@@ -189,7 +254,8 @@ pub enum SignatureError {
 #[cfg(test)]
 mod tests {
     use super::dummy_verifier::DummyVerifier;
-    use super::x509;
+    use super::{certificate_chain_expiry, x509};
+    use crate::client::http::ViaductHttpClient;
     use crate::{Collection, Record, SignatureError, Verification};
     use env_logger;
     use httpmock::MockServer;
@@ -229,7 +295,10 @@ mod tests {
         let root_hash = "3C:01:44:6A:BE:90:36:CE:A9:A0:9A:CA:A3:A5:20:AC:62:8F:20:A7:AE:32:CE:86:1C:B2:EF:B7:0F:A0:C7:45";
 
         for verifier in &verifiers {
-            assert_eq!(verifier.verify(&collection, root_hash), expected_result);
+            assert_eq!(
+                verifier.verify(&ViaductHttpClient {}, &collection, root_hash),
+                expected_result
+            );
         }
 
         get_pem_certificate.assert_hits(verifiers.len());
@@ -252,7 +321,9 @@ mod tests {
             timestamp: 0,
             signer: "".to_string(),
         };
-        let err = verifier.fetch_certificate_chain(&collection).unwrap_err();
+        let err = verifier
+            .fetch_certificate_chain(&ViaductHttpClient {}, &collection)
+            .unwrap_err();
         match err {
             SignatureError::MissingSignatureField() => assert!(true),
             e => assert!(false, "Unexpected error type: {:?}", e),
@@ -293,7 +364,9 @@ mod tests {
                 timestamp: 0,
                 signer: "".to_string(),
             };
-            let err = verifier.fetch_certificate_chain(&collection).unwrap_err();
+            let err = verifier
+                .fetch_certificate_chain(&ViaductHttpClient {}, &collection)
+                .unwrap_err();
             assert!(err.to_string().contains(error), "{}", err.to_string());
         }
 
@@ -432,6 +505,12 @@ HszKVANqXQIxAIygMaeTiD9figEusmHMthBdFoIoHk31x4MHukAy+TWZ863X6/V2
         let march_12_2021 = Duration::from_secs(1615559719);
         MockClock::set_time(march_12_2021);
 
+        // Earliest expiry of the chain (April 24, 2021).
+        assert_eq!(
+            certificate_chain_expiry(VALID_CERTIFICATE.as_bytes()).unwrap(),
+            1619276645
+        );
+
         verify_signature(
             &mock_server,
             Collection {
@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use super::{Collection, SignatureError, Verification};
+use super::{Collection, HttpClient, SignatureError, Verification};
 use log::debug;
 
 pub struct DummyVerifier {}
@@ -20,7 +20,12 @@ impl Verification for DummyVerifier {
         Ok(()) // unreachable.
     }
 
-    fn verify(&self, _collection: &Collection, _: &str) -> Result<(), SignatureError> {
+    fn verify(
+        &self,
+        _: &dyn HttpClient,
+        _collection: &Collection,
+        _: &str,
+    ) -> Result<(), SignatureError> {
         debug!("default verifier implementation");
         Ok(())
     }
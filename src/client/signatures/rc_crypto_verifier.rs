@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use super::{x509, SignatureError, Verification};
+use super::{verify_until_expiry, x509, Collection, HttpClient, SignatureError, Verification};
 use rc_crypto::digest::{digest, SHA256};
 use rc_crypto::signature;
 use x509_parser::time::ASN1Time;
@@ -82,4 +82,13 @@ impl Verification for RcCryptoVerifier {
             Err(err) => Err(SignatureError::MismatchError(err.to_string())),
         }
     }
+
+    fn verify_with_expiry(
+        &self,
+        http: &dyn HttpClient,
+        collection: &Collection,
+        root_hash: &str,
+    ) -> Result<Option<u64>, SignatureError> {
+        verify_until_expiry(self, http, collection, root_hash)
+    }
 }
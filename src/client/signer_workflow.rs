@@ -0,0 +1,366 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Commands of the signer review workflow.
+//!
+//! Changes are made in the *workspace* bucket (eg. `main-workspace`). Once a review is requested,
+//! the server copies them to the *preview* bucket (eg. `main-preview`). Approving the review
+//! publishes them signed in the destination bucket (eg. `main`).
+//!
+//! With the `write_api` feature.
+
+use super::kinto_http::{get_collection, list_records, KintoError, KintoObject, ListParams};
+use super::kinto_write::{AuthenticatedClient, Precondition};
+use super::Record;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+
+type Result<T> = std::result::Result<T, KintoError>;
+
+/// Status of a collection in the review workflow.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReviewStatus {
+    WorkInProgress,
+    ToReview,
+    ToSign,
+    Signed,
+    ToRollback,
+    ToResign,
+    #[serde(other)]
+    Unknown,
+}
+
+/// Review metadata, set by the server on the workspace collection.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct ReviewInfo {
+    pub status: Option<ReviewStatus>,
+    pub last_modified: u64,
+    pub last_edit_by: Option<String>,
+    pub last_edit_date: Option<String>,
+    pub last_review_request_by: Option<String>,
+    pub last_review_request_date: Option<String>,
+    pub last_editor_comment: Option<String>,
+    pub last_review_by: Option<String>,
+    pub last_review_date: Option<String>,
+    pub last_reviewer_comment: Option<String>,
+    pub last_signature_by: Option<String>,
+    pub last_signature_date: Option<String>,
+}
+
+/// Pending changes of the workspace bucket, compared to the preview bucket.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReviewDiff {
+    pub created: Vec<Record>,
+    pub updated: Vec<Record>,
+    pub deleted: Vec<Record>,
+}
+
+impl ReviewDiff {
+    pub fn is_empty(&self) -> bool {
+        self.created.is_empty() && self.updated.is_empty() && self.deleted.is_empty()
+    }
+}
+
+impl AuthenticatedClient {
+    /// Fetches the review metadata of the specified workspace collection.
+    pub fn review_info(&self, bid: &str, cid: &str) -> Result<ReviewInfo> {
+        let metadata = get_collection(self, self.server_url(), bid, cid)?;
+        serde_json::from_value(metadata).map_err(KintoError::InvalidResponseBody)
+    }
+
+    /// Requests a review of the changes (`to-review`), with an optional comment for the reviewer.
+    pub fn request_review(
+        &self,
+        bid: &str,
+        cid: &str,
+        comment: Option<&str>,
+    ) -> Result<ReviewInfo> {
+        let mut data = json!({ "status": "to-review" });
+        if let Some(comment) = comment {
+            data["last_editor_comment"] = json!(comment);
+        }
+        self.set_review_status(bid, cid, data)
+    }
+
+    /// Approves the changes (`to-sign`), and publishes them in the destination bucket.
+    ///
+    /// # Errors
+    /// If the collection was modified since `expected` (see [`ReviewInfo::last_modified`]),
+    /// a [`KintoError::PreconditionFailed`] is returned.
+    pub fn approve(&self, bid: &str, cid: &str, expected: u64) -> Result<ReviewInfo> {
+        let data = json!({ "status": "to-sign" });
+        let metadata = self.patch_collection(bid, cid, &data, Precondition::IfMatch(expected))?;
+        serde_json::from_value(metadata).map_err(KintoError::InvalidResponseBody)
+    }
+
+    /// Declines the review (`work-in-progress`), with an optional comment for the editor.
+    pub fn decline(&self, bid: &str, cid: &str, comment: Option<&str>) -> Result<ReviewInfo> {
+        let mut data = json!({ "status": "work-in-progress" });
+        if let Some(comment) = comment {
+            data["last_reviewer_comment"] = json!(comment);
+        }
+        self.set_review_status(bid, cid, data)
+    }
+
+    /// Cancels the pending changes (`to-rollback`), and restores the workspace and preview
+    /// buckets to the content of the destination bucket.
+    pub fn rollback(&self, bid: &str, cid: &str) -> Result<ReviewInfo> {
+        self.set_review_status(bid, cid, json!({ "status": "to-rollback" }))
+    }
+
+    /// Compares the records of the workspace and preview buckets, in order to show the changes
+    /// of the collection before approving them.
+    pub fn review_diff(
+        &self,
+        workspace_bid: &str,
+        preview_bid: &str,
+        cid: &str,
+    ) -> Result<ReviewDiff> {
+        let params = ListParams::new();
+        let workspace = list_records(self, self.server_url(), workspace_bid, cid, &params)?.data;
+        let mut preview: HashMap<String, Record> =
+            list_records(self, self.server_url(), preview_bid, cid, &params)?
+                .data
+                .into_iter()
+                .map(|r| (r.id().to_owned(), r))
+                .collect();
+
+        let mut diff = ReviewDiff::default();
+        for record in workspace {
+            match preview.remove(record.id()) {
+                None => diff.created.push(record),
+                Some(published) => {
+                    // Timestamps differ between buckets, only compare the content.
+                    if without_timestamp(&record) != without_timestamp(&published) {
+                        diff.updated.push(record);
+                    }
+                }
+            }
+        }
+        diff.deleted = preview.into_iter().map(|(_, r)| r).collect();
+        diff.deleted.sort_by(|a, b| a.id().cmp(b.id()));
+
+        Ok(diff)
+    }
+
+    fn set_review_status(&self, bid: &str, cid: &str, data: KintoObject) -> Result<ReviewInfo> {
+        let metadata = self.patch_collection(bid, cid, &data, Precondition::None)?;
+        serde_json::from_value(metadata).map_err(KintoError::InvalidResponseBody)
+    }
+}
+
+fn without_timestamp(record: &Record) -> serde_json::Map<String, serde_json::Value> {
+    let mut fields = record.as_object().clone();
+    fields.remove("last_modified");
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReviewStatus;
+    use crate::client::kinto_http::KintoError;
+    use crate::client::kinto_write::{AuthenticatedClient, Authentication};
+    use httpmock::Method::{GET, PATCH};
+    use httpmock::MockServer;
+    use serde_json::json;
+    use viaduct::set_backend;
+    use viaduct_reqwest::ReqwestBackend;
+
+    fn init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let _ = set_backend(&ReqwestBackend);
+    }
+
+    fn client(mock_server: &MockServer) -> AuthenticatedClient {
+        AuthenticatedClient::builder()
+            .server_url(mock_server.url(""))
+            .authentication(Authentication::Bearer("s3cr3t".to_owned()))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_request_review_with_comment() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mut patch_mock = mock_server.mock(|when, then| {
+            when.method(PATCH)
+                .path("/buckets/main-workspace/collections/cfr")
+                .header("Authorization", "Bearer s3cr3t")
+                .json_body(json!({"data": {
+                    "status": "to-review",
+                    "last_editor_comment": "please"
+                }}));
+            then.body(
+                r#"{"data": {
+                    "id": "cfr",
+                    "last_modified": 42,
+                    "status": "to-review",
+                    "last_review_request_by": "account:alice",
+                    "last_editor_comment": "please"
+                }}"#,
+            );
+        });
+
+        let info = client(&mock_server)
+            .request_review("main-workspace", "cfr", Some("please"))
+            .unwrap();
+
+        assert_eq!(info.status, Some(ReviewStatus::ToReview));
+        assert_eq!(info.last_modified, 42);
+        assert_eq!(
+            info.last_review_request_by.as_deref(),
+            Some("account:alice")
+        );
+        assert_eq!(info.last_editor_comment.as_deref(), Some("please"));
+
+        patch_mock.assert();
+        patch_mock.delete();
+    }
+
+    #[test]
+    fn test_approve_fails_if_modified_meanwhile() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mut patch_mock = mock_server.mock(|when, then| {
+            when.method(PATCH)
+                .path("/buckets/main-workspace/collections/cfr")
+                .header("If-Match", "\"42\"")
+                .json_body(json!({"data": {"status": "to-sign"}}));
+            then.status(412).body(
+                r#"{
+                    "code": 412,
+                    "errno": 114,
+                    "error": "Precondition Failed",
+                    "message": "Resource was modified meanwhile"
+                }"#,
+            );
+        });
+
+        let err = client(&mock_server)
+            .approve("main-workspace", "cfr", 42)
+            .unwrap_err();
+
+        match err {
+            KintoError::PreconditionFailed { .. } => (),
+            e => assert!(false, "Unexpected error type: {:?}", e),
+        };
+
+        patch_mock.assert();
+        patch_mock.delete();
+    }
+
+    #[test]
+    fn test_decline_and_rollback() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mut decline_mock = mock_server.mock(|when, then| {
+            when.method(PATCH)
+                .path("/buckets/main-workspace/collections/cfr")
+                .json_body(json!({"data": {
+                    "status": "work-in-progress",
+                    "last_reviewer_comment": "typo"
+                }}));
+            then.body(
+                r#"{"data": {"id": "cfr", "last_modified": 43, "status": "work-in-progress"}}"#,
+            );
+        });
+        let mut rollback_mock = mock_server.mock(|when, then| {
+            when.method(PATCH)
+                .path("/buckets/main-workspace/collections/cfr")
+                .json_body(json!({"data": {"status": "to-rollback"}}));
+            then.body(r#"{"data": {"id": "cfr", "last_modified": 44, "status": "signed"}}"#);
+        });
+
+        let client = client(&mock_server);
+        let declined = client
+            .decline("main-workspace", "cfr", Some("typo"))
+            .unwrap();
+        let rolledback = client.rollback("main-workspace", "cfr").unwrap();
+
+        assert_eq!(declined.status, Some(ReviewStatus::WorkInProgress));
+        assert_eq!(rolledback.status, Some(ReviewStatus::Signed));
+
+        decline_mock.assert();
+        rollback_mock.assert();
+        decline_mock.delete();
+        rollback_mock.delete();
+    }
+
+    #[test]
+    fn test_review_info_unknown_status() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mut get_mock = mock_server.mock(|when, then| {
+            when.method(GET)
+                .path("/buckets/main-workspace/collections/cfr")
+                .header("Authorization", "Bearer s3cr3t");
+            then.body(r#"{"data": {"id": "cfr", "last_modified": 42, "status": "frozen"}}"#);
+        });
+
+        let info = client(&mock_server)
+            .review_info("main-workspace", "cfr")
+            .unwrap();
+
+        assert_eq!(info.status, Some(ReviewStatus::Unknown));
+        assert_eq!(info.last_review_request_by, None);
+
+        get_mock.assert();
+        get_mock.delete();
+    }
+
+    #[test]
+    fn test_review_diff() {
+        init();
+
+        let mock_server = MockServer::start();
+        let mut workspace_mock = mock_server.mock(|when, then| {
+            when.method(GET)
+                .path("/buckets/main-workspace/collections/cfr/records")
+                .header("Authorization", "Bearer s3cr3t");
+            then.body(
+                r#"{"data": [
+                    {"id": "new", "last_modified": 30, "foo": 1},
+                    {"id": "changed", "last_modified": 20, "foo": 2},
+                    {"id": "same", "last_modified": 10, "foo": 3}
+                ]}"#,
+            );
+        });
+        let mut preview_mock = mock_server.mock(|when, then| {
+            when.method(GET)
+                .path("/buckets/main-preview/collections/cfr/records")
+                .header("Authorization", "Bearer s3cr3t");
+            then.body(
+                r#"{"data": [
+                    {"id": "changed", "last_modified": 15, "foo": 0},
+                    {"id": "same", "last_modified": 11, "foo": 3},
+                    {"id": "gone", "last_modified": 5, "foo": 4}
+                ]}"#,
+            );
+        });
+
+        let diff = client(&mock_server)
+            .review_diff("main-workspace", "main-preview", "cfr")
+            .unwrap();
+
+        assert!(!diff.is_empty());
+        assert_eq!(diff.created.len(), 1);
+        assert_eq!(diff.created[0].id(), "new");
+        assert_eq!(diff.updated.len(), 1);
+        assert_eq!(diff.updated[0]["foo"].as_u64(), Some(2));
+        assert_eq!(diff.deleted.len(), 1);
+        assert_eq!(diff.deleted[0].id(), "gone");
+
+        workspace_mock.assert();
+        preview_mock.assert();
+        workspace_mock.delete();
+        preview_mock.delete();
+    }
+}
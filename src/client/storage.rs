@@ -2,10 +2,21 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+pub mod bounded_storage;
+#[cfg(feature = "compression")]
+pub mod compressed_storage;
 pub mod dummy_storage;
+#[cfg(feature = "encryption")]
+pub mod encrypted_storage;
 pub mod file_storage;
+mod lru;
 pub mod memory_storage;
+pub mod record_storage;
+#[cfg(feature = "sqlite_storage")]
+pub mod sqlite_storage;
+pub mod tiered_storage;
 
+use super::{Codec, Collection, Record};
 use thiserror::Error;
 
 /// A trait for giving a type a custom storage implementation
@@ -41,6 +52,116 @@ pub trait Storage: Send {
     ///
     /// If an error occurs while reading, a [`StorageError::ReadError`] is returned.
     fn retrieve(&self, key: &str) -> Result<Vec<u8>, StorageError>;
+
+    /// Delete the value of a given key. Deleting a key that does not exist is not an error.
+    ///
+    /// # Errors
+    /// If an error occurs while deleting, a [`StorageError::WriteError`] is returned.
+    ///
+    /// The default implementation does not support deleting, and always returns
+    /// a [`StorageError::WriteError`].
+    fn delete(&mut self, key: &str) -> Result<(), StorageError> {
+        Err(StorageError::WriteError(format!(
+            "cannot delete {}: not supported by this storage",
+            key
+        )))
+    }
+
+    /// List the stored keys, optionally only those starting with `prefix`.
+    ///
+    /// # Errors
+    /// If an error occurs while listing, a [`StorageError::ReadError`] is returned.
+    ///
+    /// The default implementation does not support listing, and always returns
+    /// a [`StorageError::ReadError`].
+    fn keys(&self, _prefix: Option<&str>) -> Result<Vec<String>, StorageError> {
+        Err(StorageError::ReadError(
+            "listing keys is not supported by this storage".to_string(),
+        ))
+    }
+
+    /// Delete all the stored values.
+    ///
+    /// # Errors
+    /// If an error occurs while deleting, a [`StorageError::WriteError`] is returned.
+    fn clear(&mut self) -> Result<(), StorageError> {
+        for key in self.keys(None)? {
+            self.delete(&key)?;
+        }
+        Ok(())
+    }
+
+    /// Return the form of `key` under which its value is stored, and that is returned by
+    /// [`Storage::keys`] (eg. with unsupported characters replaced). Two keys with the same
+    /// canonical form refer to the same value.
+    fn canonical_key(&self, key: &str) -> String {
+        key.to_string()
+    }
+
+    /// Return the record-level interface of this storage, if supported.
+    ///
+    /// When available, the client reads and writes collections through it instead of
+    /// storing them as serialized values.
+    fn as_collection_storage(&self) -> Option<&dyn CollectionStorage> {
+        None
+    }
+
+    /// Mutable variant of [`Storage::as_collection_storage`].
+    fn as_collection_storage_mut(&mut self) -> Option<&mut dyn CollectionStorage> {
+        None
+    }
+}
+
+/// An extended storage interface, where the records of collections are stored individually.
+///
+/// This allows synchronizations to only write the changes instead of the whole collection.
+/// The `codec` of the client is passed to serialize the stored values, if any.
+pub trait CollectionStorage {
+    /// Replace the collection stored under `key`, including all its records.
+    ///
+    /// # Errors
+    /// If an error occurs while storing, a [`StorageError::WriteError`] is returned.
+    fn store_collection(
+        &mut self,
+        key: &str,
+        collection: &Collection,
+        codec: &dyn Codec,
+    ) -> Result<(), StorageError>;
+
+    /// Retrieve the collection stored under `key`.
+    ///
+    /// # Errors
+    /// If the specified key does not exist, a [`StorageError::KeyNotFound`] is returned.
+    ///
+    /// If an error occurs while reading, a [`StorageError::ReadError`] is returned.
+    fn retrieve_collection(&self, key: &str, codec: &dyn Codec)
+        -> Result<Collection, StorageError>;
+
+    /// Replace the collection stored under `key` by `merged`, the result of the `changes`
+    /// (upserts and tombstones) on the stored records, eg. to only write these changes.
+    ///
+    /// The update must be atomic: on error, the stored collection is left unchanged.
+    ///
+    /// # Errors
+    /// If an error occurs while storing, a [`StorageError::WriteError`] is returned.
+    fn apply_changes(
+        &mut self,
+        key: &str,
+        merged: &Collection,
+        changes: &[Record],
+        codec: &dyn Codec,
+    ) -> Result<(), StorageError>;
+
+    /// Retrieve a single record of the collection stored under `key`.
+    ///
+    /// # Errors
+    /// If an error occurs while reading, a [`StorageError::ReadError`] is returned.
+    fn retrieve_record(
+        &self,
+        key: &str,
+        id: &str,
+        codec: &dyn Codec,
+    ) -> Result<Option<Record>, StorageError>;
 }
 
 #[derive(Debug, PartialEq, Error)]
@@ -51,4 +172,10 @@ pub enum StorageError {
     ReadError(String),
     #[error("key could not be found: {key}")]
     KeyNotFound { key: String },
+    #[error("timed out waiting for lock on: {key}")]
+    LockTimeout { key: String },
+    #[error("stored data is corrupted: {0}")]
+    CorruptedData(String),
+    #[error("stored data has an unsupported schema: {0}")]
+    UnsupportedSchema(String),
 }
@@ -0,0 +1,259 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use {
+    super::lru::{lock, Lru},
+    super::{Storage, StorageError},
+    log::{debug, info},
+    std::sync::{Mutex, MutexGuard},
+};
+
+/// A storage wrapper which limits the total size of the values stored in another storage,
+/// by evicting the least recently used keys.
+///
+/// Evicted collections are synchronized again on the next `.get()` (with `sync_if_empty`).
+/// The existing values are accounted for when the storage is created, but the order of
+/// accesses is only tracked while it is in use. Values are evicted once the new value
+/// is stored, so that a failed write does not lose them.
+///
+/// The record-level interface of the wrapped storage is not forwarded, since its writes
+/// would not be accounted for. Wrap this storage in a [`RecordStorage`](super::record_storage::RecordStorage)
+/// instead, so that records are evicted individually (and written again when missing).
+///
+/// # Examples
+/// ```rust
+/// # use remote_settings_client::Client;
+/// # use remote_settings_client::client::{BoundedStorage, FileStorage};
+/// # fn main() {
+/// let storage = BoundedStorage::new(Box::new(FileStorage::default()), 5 * 1024 * 1024).unwrap();
+/// let client = Client::builder()
+///   .collection_name("cid")
+///   .storage(Box::new(storage))
+///   .build()
+///   .unwrap();
+/// # }
+/// ```
+pub struct BoundedStorage {
+    inner: Box<dyn Storage>,
+    max_size: usize,
+    usage: Mutex<Lru<()>>,
+}
+
+impl BoundedStorage {
+    /// Wrap the storage, and evict values if those already stored exceed `max_size` (in bytes).
+    ///
+    /// # Errors
+    /// If the existing values cannot be listed or read, a [`StorageError::ReadError`] is returned.
+    pub fn new(inner: Box<dyn Storage>, max_size: usize) -> Result<Self, StorageError> {
+        let mut usage = Lru::default();
+        for key in inner.keys(None)? {
+            let size = inner.retrieve(&key)?.len();
+            usage.insert(&key, (), size);
+        }
+        let mut storage = BoundedStorage {
+            inner,
+            max_size,
+            usage: Mutex::new(usage),
+        };
+        storage.evict(None)?;
+        Ok(storage)
+    }
+
+    /// Total size of the stored values, in bytes.
+    pub fn size(&self) -> usize {
+        self.usage().size()
+    }
+
+    fn usage(&self) -> MutexGuard<'_, Lru<()>> {
+        lock(&self.usage)
+    }
+
+    /// Evict the least recently used values (but `except`) until the size is within the limit.
+    fn evict(&mut self, except: Option<&str>) -> Result<(), StorageError> {
+        loop {
+            let lru = {
+                let usage = self.usage();
+                if usage.size() <= self.max_size {
+                    return Ok(());
+                }
+                usage.least_recently_used(except)
+            };
+            match lru {
+                Some(key) => {
+                    info!("Evict {} from storage", key);
+                    self.inner.delete(&key)?;
+                    self.usage().remove(&key);
+                }
+                None => return Ok(()),
+            }
+        }
+    }
+}
+
+impl Storage for BoundedStorage {
+    fn store(&mut self, key: &str, value: Vec<u8>) -> Result<(), StorageError> {
+        let size = value.len();
+        if size > self.max_size {
+            return Err(StorageError::WriteError(format!(
+                "{} ({} bytes) exceeds the storage limit ({} bytes)",
+                key, size, self.max_size
+            )));
+        }
+        let canonical = self.inner.canonical_key(key);
+        self.inner.store(key, value)?;
+        self.usage().insert(&canonical, (), size);
+        self.evict(Some(&canonical))?;
+        debug!("Storage holds {} bytes", self.size());
+        Ok(())
+    }
+
+    fn retrieve(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        let value = self.inner.retrieve(key)?;
+        self.usage().get(&self.inner.canonical_key(key));
+        Ok(value)
+    }
+
+    fn delete(&mut self, key: &str) -> Result<(), StorageError> {
+        self.inner.delete(key)?;
+        self.usage().remove(&self.inner.canonical_key(key));
+        Ok(())
+    }
+
+    fn keys(&self, prefix: Option<&str>) -> Result<Vec<String>, StorageError> {
+        self.inner.keys(prefix)
+    }
+
+    fn clear(&mut self) -> Result<(), StorageError> {
+        self.inner.clear()?;
+        *self.usage() = Lru::default();
+        Ok(())
+    }
+
+    fn canonical_key(&self, key: &str) -> String {
+        self.inner.canonical_key(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BoundedStorage;
+    use crate::client::storage::{
+        file_storage::FileStorage, memory_storage::MemoryStorage, Storage, StorageError,
+    };
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    fn sorted_keys(storage: &BoundedStorage) -> Vec<String> {
+        let mut keys = storage.keys(None).unwrap();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn test_least_recently_used_are_evicted() {
+        let mut storage = BoundedStorage::new(Box::new(MemoryStorage::new()), 10).unwrap();
+
+        storage.store("a", vec![0; 4]).unwrap();
+        storage.store("b", vec![0; 4]).unwrap();
+        storage.retrieve("a").unwrap();
+        storage.store("c", vec![0; 4]).unwrap();
+
+        assert_eq!(sorted_keys(&storage), vec!["a", "c"]);
+        assert_eq!(storage.size(), 8);
+
+        // Overwriting a value only accounts for the difference.
+        storage.store("c", vec![0; 6]).unwrap();
+        assert_eq!(sorted_keys(&storage), vec!["a", "c"]);
+        assert_eq!(storage.size(), 10);
+
+        storage.delete("a").unwrap();
+        assert_eq!(storage.size(), 6);
+    }
+
+    #[test]
+    fn test_value_larger_than_limit() {
+        let mut storage = BoundedStorage::new(Box::new(MemoryStorage::new()), 10).unwrap();
+        storage.store("a", vec![0; 4]).unwrap();
+
+        assert!(matches!(
+            storage.store("b", vec![0; 11]),
+            Err(StorageError::WriteError(_))
+        ));
+        assert_eq!(sorted_keys(&storage), vec!["a"]);
+    }
+
+    #[test]
+    fn test_existing_values_are_accounted() {
+        let mut inner = MemoryStorage::new();
+        inner.store("a", vec![0; 4]).unwrap();
+        inner.store("b", vec![0; 4]).unwrap();
+        inner.store("c", vec![0; 4]).unwrap();
+
+        let storage = BoundedStorage::new(Box::new(inner), 10).unwrap();
+
+        assert_eq!(storage.keys(None).unwrap().len(), 2);
+        assert_eq!(storage.size(), 8);
+    }
+
+    #[test]
+    fn test_keys_are_accounted_in_canonical_form() {
+        let folder = std::env::temp_dir().join("rs-bounded-storage");
+        let _ = remove_dir_all(&folder);
+        create_dir_all(&folder).unwrap();
+        let mut inner = FileStorage {
+            folder: folder.clone(),
+            ..FileStorage::default()
+        };
+        inner.store("main/cfr", vec![0; 4]).unwrap();
+
+        let mut storage = BoundedStorage::new(Box::new(inner), 10).unwrap();
+        storage.store("main/cfr", vec![0; 6]).unwrap();
+
+        assert_eq!(storage.size(), 6);
+        assert_eq!(sorted_keys(&storage), vec!["main+cfr"]);
+        remove_dir_all(&folder).unwrap();
+    }
+
+    /// A storage where writes of some keys fail.
+    struct FailingStorage {
+        inner: MemoryStorage,
+        failing: &'static str,
+    }
+
+    impl Storage for FailingStorage {
+        fn store(&mut self, key: &str, value: Vec<u8>) -> Result<(), StorageError> {
+            if key == self.failing {
+                return Err(StorageError::WriteError("disk full".to_string()));
+            }
+            self.inner.store(key, value)
+        }
+
+        fn retrieve(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+            self.inner.retrieve(key)
+        }
+
+        fn delete(&mut self, key: &str) -> Result<(), StorageError> {
+            self.inner.delete(key)
+        }
+
+        fn keys(&self, prefix: Option<&str>) -> Result<Vec<String>, StorageError> {
+            self.inner.keys(prefix)
+        }
+    }
+
+    #[test]
+    fn test_failed_write_does_not_evict() {
+        let inner = FailingStorage {
+            inner: MemoryStorage::new(),
+            failing: "c",
+        };
+        let mut storage = BoundedStorage::new(Box::new(inner), 10).unwrap();
+        storage.store("a", vec![0; 4]).unwrap();
+        storage.store("b", vec![0; 4]).unwrap();
+
+        assert!(storage.store("c", vec![0; 4]).is_err());
+
+        assert_eq!(sorted_keys(&storage), vec!["a", "b"]);
+        assert_eq!(storage.size(), 8);
+    }
+}
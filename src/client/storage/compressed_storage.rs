@@ -0,0 +1,178 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use {
+    super::{Storage, StorageError},
+    flate2::{read::GzDecoder, write::GzEncoder, Compression},
+    log::debug,
+    std::io::prelude::*,
+};
+
+// Header of stored values: magic bytes followed by the format byte.
+const MAGIC: &[u8] = b"RSZ";
+const FORMAT_RAW: u8 = 0;
+const FORMAT_GZIP: u8 = 1;
+
+/// A storage wrapper which transparently compresses the values (gzip) of another storage.
+///
+/// Stored values carry a format header. Values written without this wrapper
+/// (eg. before enabling compression) are still read as is.
+///
+/// With the `compression` feature.
+///
+/// # Examples
+/// ```rust
+/// # use remote_settings_client::Client;
+/// # use remote_settings_client::client::{CompressedStorage, FileStorage};
+/// # fn main() {
+/// let client = Client::builder()
+///   .collection_name("cid")
+///   .storage(Box::new(CompressedStorage::new(Box::new(FileStorage::default()))))
+///   .build()
+///   .unwrap();
+/// # }
+/// ```
+pub struct CompressedStorage {
+    inner: Box<dyn Storage>,
+    level: u32,
+    min_size: usize,
+}
+
+impl CompressedStorage {
+    pub fn new(inner: Box<dyn Storage>) -> Self {
+        CompressedStorage {
+            inner,
+            level: Compression::default().level(),
+            min_size: 512,
+        }
+    }
+
+    /// Compression level, from 0 (none) to 9 (best).
+    pub fn level(mut self, level: u32) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Values smaller than this size (in bytes) are stored uncompressed.
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+}
+
+impl Storage for CompressedStorage {
+    fn store(&mut self, key: &str, value: Vec<u8>) -> Result<(), StorageError> {
+        let mut stored = MAGIC.to_vec();
+        if value.len() < self.min_size {
+            stored.push(FORMAT_RAW);
+            stored.extend(value);
+        } else {
+            stored.push(FORMAT_GZIP);
+            let mut encoder = GzEncoder::new(stored, Compression::new(self.level));
+            encoder.write_all(&value)?;
+            stored = encoder.finish()?;
+            debug!(
+                "Compressed {} from {} to {} bytes",
+                key,
+                value.len(),
+                stored.len()
+            );
+        }
+        self.inner.store(key, stored)
+    }
+
+    fn retrieve(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        let stored = self.inner.retrieve(key)?;
+        if !stored.starts_with(MAGIC) || stored.len() <= MAGIC.len() {
+            debug!("Read uncompressed value of {}", key);
+            return Ok(stored);
+        }
+        let payload = &stored[MAGIC.len() + 1..];
+        match stored[MAGIC.len()] {
+            FORMAT_RAW => Ok(payload.to_vec()),
+            FORMAT_GZIP => {
+                let mut value = Vec::new();
+                GzDecoder::new(payload)
+                    .read_to_end(&mut value)
+                    .map_err(|err| {
+                        StorageError::ReadError(format!("cannot decompress {}: {}", key, err))
+                    })?;
+                Ok(value)
+            }
+            format => Err(StorageError::ReadError(format!(
+                "unknown compression format {} for {}",
+                format, key
+            ))),
+        }
+    }
+
+    fn delete(&mut self, key: &str) -> Result<(), StorageError> {
+        self.inner.delete(key)
+    }
+
+    fn keys(&self, prefix: Option<&str>) -> Result<Vec<String>, StorageError> {
+        self.inner.keys(prefix)
+    }
+
+    fn clear(&mut self) -> Result<(), StorageError> {
+        self.inner.clear()
+    }
+
+    fn canonical_key(&self, key: &str) -> String {
+        self.inner.canonical_key(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CompressedStorage;
+    use crate::client::storage::{memory_storage::MemoryStorage, Storage, StorageError};
+
+    #[test]
+    fn test_compressed_values_are_smaller() {
+        let mut storage = CompressedStorage::new(Box::new(MemoryStorage::new())).min_size(10);
+        let value = "{\"id\": \"abc\"}".repeat(100).into_bytes();
+
+        storage.store("key", value.clone()).unwrap();
+
+        assert_eq!(storage.retrieve("key").unwrap(), value);
+        assert!(storage.inner.retrieve("key").unwrap().len() < value.len() / 10);
+    }
+
+    #[test]
+    fn test_small_values_are_not_compressed() {
+        let mut storage = CompressedStorage::new(Box::new(MemoryStorage::new()));
+
+        storage.store("key", b"{}".to_vec()).unwrap();
+
+        assert_eq!(storage.inner.retrieve("key").unwrap(), b"RSZ\x00{}");
+        assert_eq!(storage.retrieve("key").unwrap(), b"{}");
+    }
+
+    #[test]
+    fn test_uncompressed_values_are_read_as_is() {
+        let mut inner = MemoryStorage::new();
+        inner.store("key", b"{\"legacy\": true}".to_vec()).unwrap();
+        let storage = CompressedStorage::new(Box::new(inner));
+
+        assert_eq!(storage.retrieve("key").unwrap(), b"{\"legacy\": true}");
+    }
+
+    #[test]
+    fn test_corrupted_values_fail_to_read() {
+        let mut inner = MemoryStorage::new();
+        inner.store("gzip", b"RSZ\x01garbage".to_vec()).unwrap();
+        inner.store("unknown", b"RSZ\x09{}".to_vec()).unwrap();
+        let storage = CompressedStorage::new(Box::new(inner));
+
+        assert!(matches!(
+            storage.retrieve("gzip"),
+            Err(StorageError::ReadError(_))
+        ));
+        assert!(matches!(
+            storage.retrieve("unknown"),
+            Err(StorageError::ReadError(_))
+        ));
+    }
+}
@@ -16,4 +16,12 @@ impl Storage for DummyStorage {
             key: key.to_string(),
         })
     }
+
+    fn delete(&mut self, _key: &str) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    fn keys(&self, _prefix: Option<&str>) -> Result<Vec<String>, StorageError> {
+        Ok(Vec::new())
+    }
 }
@@ -0,0 +1,282 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use {
+    super::{Storage, StorageError},
+    log::{debug, error},
+    ring::aead::{
+        Aad, Algorithm, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, CHACHA20_POLY1305, NONCE_LEN,
+    },
+    ring::rand::{SecureRandom, SystemRandom},
+};
+
+// Header of stored values: magic bytes, cipher byte, and nonce.
+const MAGIC: &[u8] = b"RSE";
+const HEADER_LEN: usize = 3 + 1 + NONCE_LEN;
+
+// Length of the encryption keys, in bytes.
+const KEY_LEN: usize = 32;
+
+/// Authenticated encryption algorithm of the stored values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cipher {
+    Aes256Gcm,
+    ChaCha20Poly1305,
+}
+
+impl Cipher {
+    fn id(self) -> u8 {
+        match self {
+            Cipher::Aes256Gcm => 1,
+            Cipher::ChaCha20Poly1305 => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Cipher::Aes256Gcm),
+            2 => Some(Cipher::ChaCha20Poly1305),
+            _ => None,
+        }
+    }
+
+    fn algorithm(self) -> &'static Algorithm {
+        match self {
+            Cipher::Aes256Gcm => &AES_256_GCM,
+            Cipher::ChaCha20Poly1305 => &CHACHA20_POLY1305,
+        }
+    }
+}
+
+/// A trait for supplying the encryption key (eg. from the OS keychain).
+///
+/// Any closure returning the key can be used.
+pub trait KeyProvider: Send {
+    /// Return the 256 bits key.
+    ///
+    /// # Errors
+    /// If the key is not available, the error is returned by the storage operation.
+    fn key(&self) -> Result<[u8; KEY_LEN], StorageError>;
+}
+
+impl<F> KeyProvider for F
+where
+    F: Fn() -> Result<[u8; KEY_LEN], StorageError> + Send,
+{
+    fn key(&self) -> Result<[u8; KEY_LEN], StorageError> {
+        self()
+    }
+}
+
+/// A storage wrapper which encrypts the values of another storage (authenticated encryption).
+///
+/// Values are bound to their key: a value moved under another key fails to decrypt.
+/// On tampering, wrong encryption key, or unencrypted values, reads fail with
+/// a [`StorageError::ReadError`].
+///
+/// With the `encryption` feature.
+///
+/// # Examples
+/// ```rust
+/// # use remote_settings_client::Client;
+/// # use remote_settings_client::client::{EncryptedStorage, FileStorage, StorageError};
+/// # fn main() {
+/// let key_provider = || -> Result<[u8; 32], StorageError> {
+///     Ok([42; 32]) // Read from the OS keychain instead.
+/// };
+/// let client = Client::builder()
+///   .collection_name("cid")
+///   .storage(Box::new(EncryptedStorage::new(
+///     Box::new(FileStorage::default()),
+///     Box::new(key_provider),
+///   )))
+///   .build()
+///   .unwrap();
+/// # }
+/// ```
+pub struct EncryptedStorage {
+    inner: Box<dyn Storage>,
+    key_provider: Box<dyn KeyProvider>,
+    cipher: Cipher,
+    rng: SystemRandom,
+}
+
+impl EncryptedStorage {
+    pub fn new(inner: Box<dyn Storage>, key_provider: Box<dyn KeyProvider>) -> Self {
+        EncryptedStorage {
+            inner,
+            key_provider,
+            cipher: Cipher::Aes256Gcm,
+            rng: SystemRandom::new(),
+        }
+    }
+
+    /// Algorithm used to encrypt new values. Values are always decrypted with the algorithm
+    /// they were encrypted with.
+    pub fn cipher(mut self, cipher: Cipher) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// Return the key of the cipher. An invalid key is reported with `to_error`, ie.
+    /// as a `ReadError` or a `WriteError` depending on the operation.
+    fn sealing_key(
+        &self,
+        cipher: Cipher,
+        to_error: fn(String) -> StorageError,
+    ) -> Result<LessSafeKey, StorageError> {
+        let key = self.key_provider.key()?;
+        let unbound = UnboundKey::new(cipher.algorithm(), &key)
+            .map_err(|_| to_error("invalid encryption key".to_string()))?;
+        Ok(LessSafeKey::new(unbound))
+    }
+}
+
+impl Storage for EncryptedStorage {
+    fn store(&mut self, key: &str, value: Vec<u8>) -> Result<(), StorageError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| StorageError::WriteError("cannot generate nonce".to_string()))?;
+
+        let mut in_out = value;
+        self.sealing_key(self.cipher, StorageError::WriteError)?
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(key.as_bytes()),
+                &mut in_out,
+            )
+            .map_err(|_| StorageError::WriteError(format!("cannot encrypt {}", key)))?;
+
+        let mut stored = Vec::with_capacity(HEADER_LEN + in_out.len());
+        stored.extend_from_slice(MAGIC);
+        stored.push(self.cipher.id());
+        stored.extend_from_slice(&nonce);
+        stored.extend(in_out);
+        debug!("Encrypted {} ({:?})", key, self.cipher);
+        self.inner.store(key, stored)
+    }
+
+    fn retrieve(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        let stored = self.inner.retrieve(key)?;
+        if stored.len() < HEADER_LEN || !stored.starts_with(MAGIC) {
+            error!("Value of {} is not encrypted", key);
+            return Err(StorageError::ReadError(format!("{} is not encrypted", key)));
+        }
+        let cipher = Cipher::from_id(stored[MAGIC.len()])
+            .ok_or_else(|| StorageError::ReadError(format!("unknown cipher for {}", key)))?;
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce.copy_from_slice(&stored[MAGIC.len() + 1..HEADER_LEN]);
+
+        let mut in_out = stored[HEADER_LEN..].to_vec();
+        let plaintext_len = self
+            .sealing_key(cipher, StorageError::ReadError)?
+            .open_in_place(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(key.as_bytes()),
+                &mut in_out,
+            )
+            .map_err(|_| {
+                error!("Value of {} could not be decrypted", key);
+                StorageError::ReadError(format!(
+                    "cannot decrypt {} (tampered data or wrong key)",
+                    key
+                ))
+            })?
+            .len();
+        in_out.truncate(plaintext_len);
+        Ok(in_out)
+    }
+
+    fn delete(&mut self, key: &str) -> Result<(), StorageError> {
+        self.inner.delete(key)
+    }
+
+    fn keys(&self, prefix: Option<&str>) -> Result<Vec<String>, StorageError> {
+        self.inner.keys(prefix)
+    }
+
+    fn clear(&mut self) -> Result<(), StorageError> {
+        self.inner.clear()
+    }
+
+    fn canonical_key(&self, key: &str) -> String {
+        self.inner.canonical_key(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cipher, EncryptedStorage, KEY_LEN};
+    use crate::client::storage::{memory_storage::MemoryStorage, Storage, StorageError};
+
+    fn storage_with_key(inner: MemoryStorage, key: [u8; KEY_LEN]) -> EncryptedStorage {
+        EncryptedStorage::new(
+            Box::new(inner),
+            Box::new(move || -> Result<[u8; KEY_LEN], StorageError> { Ok(key) }),
+        )
+    }
+
+    fn is_read_error<T>(result: Result<T, StorageError>) -> bool {
+        matches!(result, Err(StorageError::ReadError(_)))
+    }
+
+    #[test]
+    fn test_store_and_retrieve() {
+        for cipher in &[Cipher::Aes256Gcm, Cipher::ChaCha20Poly1305] {
+            let mut storage = storage_with_key(MemoryStorage::new(), [1; KEY_LEN]).cipher(*cipher);
+
+            storage.store("key", b"{\"secret\": 42}".to_vec()).unwrap();
+
+            assert_eq!(storage.retrieve("key").unwrap(), b"{\"secret\": 42}");
+            let stored = storage.inner.retrieve("key").unwrap();
+            assert!(!String::from_utf8_lossy(&stored).contains("secret"));
+        }
+    }
+
+    #[test]
+    fn test_retrieve_with_wrong_key() {
+        let mut storage = storage_with_key(MemoryStorage::new(), [1; KEY_LEN]);
+        storage.store("key", b"value".to_vec()).unwrap();
+
+        let stored = storage.inner.retrieve("key").unwrap();
+        let mut inner = MemoryStorage::new();
+        inner.store("key", stored).unwrap();
+        let other = storage_with_key(inner, [2; KEY_LEN]);
+
+        assert!(is_read_error(other.retrieve("key")));
+    }
+
+    #[test]
+    fn test_retrieve_tampered_value() {
+        let mut storage = storage_with_key(MemoryStorage::new(), [1; KEY_LEN]);
+        storage.store("key", b"value".to_vec()).unwrap();
+
+        let original = storage.inner.retrieve("key").unwrap();
+        let mut stored = original.clone();
+        let last = stored.len() - 1;
+        stored[last] ^= 1;
+        storage.inner.store("key", stored).unwrap();
+        assert!(is_read_error(storage.retrieve("key")));
+
+        // Value moved under another key.
+        storage.inner.store("other", original).unwrap();
+        assert!(is_read_error(storage.retrieve("other")));
+
+        storage.inner.store("plain", b"value".to_vec()).unwrap();
+        assert!(is_read_error(storage.retrieve("plain")));
+    }
+
+    #[test]
+    fn test_retrieve_unknown_key() {
+        let storage = storage_with_key(MemoryStorage::new(), [1; KEY_LEN]);
+
+        assert_eq!(
+            storage.retrieve("unknown").unwrap_err(),
+            StorageError::KeyNotFound {
+                key: "unknown".to_string()
+            }
+        );
+    }
+}
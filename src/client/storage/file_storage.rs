@@ -4,15 +4,34 @@
 
 use {
     super::{Storage, StorageError},
+    fs2::FileExt,
     log::{debug, error},
-    std::fs::OpenOptions,
+    std::fs::{self, File, OpenOptions},
     std::io::prelude::*,
     std::path::{Path, PathBuf},
+    std::sync::atomic::{AtomicUsize, Ordering},
+    std::thread::sleep,
+    std::time::{Duration, Instant},
 };
 
+// Distinguishes the temporary files of concurrent writes within the same process.
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A storage where each key is stored in a file of the specified folder.
+///
+/// Files are protected by advisory locks (shared for reading, exclusive for writing),
+/// so that several processes can share the same folder. The lock of each key is held on
+/// a separate `.lock` file, which is removed with the key by [`Storage::delete`] and
+/// [`Storage::clear`].
+///
+/// Characters of keys other than letters, digits, `-` and `_` are replaced by `+` in file names,
+/// and [`Storage::keys`] returns the keys in this form (eg. `main+cfr` for `main/cfr`).
+/// They can be passed as is to the other methods.
 pub struct FileStorage {
     pub folder: PathBuf,
     pub extension: String,
+    /// How long to wait for the lock of a key, before failing with [`StorageError::LockTimeout`].
+    pub lock_timeout: Duration,
 }
 
 impl Default for FileStorage {
@@ -20,6 +39,7 @@ impl Default for FileStorage {
         Self {
             folder: PathBuf::from("."),
             extension: "bin".to_string(),
+            lock_timeout: Duration::from_secs(10),
         }
     }
 }
@@ -31,9 +51,8 @@ impl From<std::io::Error> for StorageError {
 }
 
 impl FileStorage {
-    fn _pathfor(&self, key: &str) -> PathBuf {
-        let slug = key
-            .chars()
+    fn _slug(&self, key: &str) -> String {
+        key.chars()
             .map(|c| match c {
                 'a'..='z' => c,
                 'A'..='Z' => c,
@@ -42,39 +61,164 @@ impl FileStorage {
                 '_' => c,
                 _ => '+',
             })
-            .collect::<String>();
+            .collect::<String>()
+    }
 
-        let mut p = Path::new(&self.folder).join(slug);
+    fn _pathfor(&self, key: &str) -> PathBuf {
+        let mut p = Path::new(&self.folder).join(self._slug(key));
         p.set_extension(&self.extension);
 
         p
     }
+
+    fn _temppath(&self, path: &Path) -> PathBuf {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let unique = format!(
+            ".{}.{}-{}.tmp",
+            name,
+            std::process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::SeqCst)
+        );
+        path.with_file_name(unique)
+    }
+
+    /// Remove the temporary files of `path` left over by interrupted writes (eg. a crash
+    /// before the rename). Only call it with the exclusive lock of the key held.
+    fn _remove_stale_temps(&self, path: &Path) -> std::io::Result<()> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let prefix = format!(".{}.", name);
+        for entry in fs::read_dir(&self.folder)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let file_name = file_name.to_string_lossy();
+            if file_name.starts_with(&prefix) && file_name.ends_with(".tmp") {
+                debug!("Remove stale temporary file {:?}", entry.path());
+                fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn _lockpath(&self, path: &Path) -> PathBuf {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        path.with_file_name(format!(".{}.lock", name))
+    }
+
+    /// Wait for the lock of the specified file. The lock is released when the returned file is dropped.
+    fn _lock(&self, key: &str, path: &Path, exclusive: bool) -> Result<File, StorageError> {
+        let lock_path = self._lockpath(path);
+        let io_error = |err: std::io::Error| {
+            error!("Couldn't lock {:?}: {}", lock_path, err);
+            if exclusive {
+                StorageError::WriteError(err.to_string())
+            } else {
+                StorageError::ReadError(err.to_string())
+            }
+        };
+        let open = || {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&lock_path)
+        };
+        let mut file = open().map_err(io_error)?;
+
+        let started = Instant::now();
+        loop {
+            let result = if exclusive {
+                FileExt::try_lock_exclusive(&file)
+            } else {
+                FileExt::try_lock_shared(&file)
+            };
+            match result {
+                // The lock file was removed by a concurrent delete while waiting: lock the new one.
+                Ok(()) if !is_current_lock(&file, &lock_path) => {
+                    file = open().map_err(io_error)?;
+                }
+                Ok(()) => return Ok(file),
+                Err(err) if err.raw_os_error() == fs2::lock_contended_error().raw_os_error() => {
+                    if started.elapsed() >= self.lock_timeout {
+                        error!("Timed out waiting for lock {:?}", lock_path);
+                        return Err(StorageError::LockTimeout {
+                            key: key.to_string(),
+                        });
+                    }
+                    sleep(Duration::from_millis(10));
+                }
+                Err(err) => return Err(io_error(err)),
+            }
+        }
+    }
+
+    /// Write the whole value into `temp`, and flush it to disk.
+    fn _write_temp(temp: &Path, value: &[u8]) -> std::io::Result<()> {
+        let mut file = OpenOptions::new().write(true).create_new(true).open(temp)?;
+        file.write_all(value)?;
+        file.sync_all()
+    }
+}
+
+/// Flush the directory entries (eg. after a rename), so that they survive a crash.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> std::io::Result<()> {
+    // Directories cannot be opened (nor synced) on this platform.
+    Ok(())
+}
+
+/// Whether the locked `file` is still the one at `lock_path`, ie. it was not removed
+/// (and possibly created again) by a concurrent delete.
+#[cfg(unix)]
+fn is_current_lock(file: &File, lock_path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), fs::metadata(lock_path)) {
+        (Ok(locked), Ok(current)) => locked.dev() == current.dev() && locked.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_current_lock(_file: &File, lock_path: &Path) -> bool {
+    // Files cannot be identified on this platform: only detect removals.
+    lock_path.exists()
 }
 
 impl Storage for FileStorage {
     fn store(&mut self, key: &str, value: Vec<u8>) -> Result<(), StorageError> {
         let path = self._pathfor(&key);
-        match OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .create(true)
-            .open(&path)
-        {
-            Err(err) => {
-                error!("Couldn't open or create {:?}: {}", path, err);
-                Err(StorageError::WriteError(err.to_string()))
-            }
-            Ok(mut file) => {
-                file.write_all(&value)?;
-                file.sync_all()?;
-                debug!("Wrote {} ({} bytes) into {:?}", key, value.len(), path);
-                Ok(())
-            }
+        let _lock = self._lock(key, &path, true)?;
+        // Write into a temporary file of the same folder, and then move it over the
+        // target, so that a crash in the middle of a write never leaves a truncated file.
+        let temp = self._temppath(&path);
+        let result = self
+            ._remove_stale_temps(&path)
+            .and_then(|_| Self::_write_temp(&temp, &value))
+            .and_then(|_| fs::rename(&temp, &path));
+        if let Err(err) = result {
+            error!("Couldn't write {:?}: {}", path, err);
+            let _ = fs::remove_file(&temp);
+            return Err(StorageError::WriteError(err.to_string()));
         }
+        sync_dir(&self.folder)?;
+        debug!("Wrote {} ({} bytes) into {:?}", key, value.len(), path);
+        Ok(())
     }
 
     fn retrieve(&self, key: &str) -> Result<Vec<u8>, StorageError> {
         let path = self._pathfor(&key);
+        if !path.exists() {
+            debug!("Couldn't find {:?}", path);
+            return Err(StorageError::KeyNotFound {
+                key: key.to_string(),
+            });
+        }
+        let _lock = self._lock(key, &path, false)?;
         let mut file = match OpenOptions::new().read(true).write(false).open(&path) {
             Ok(file) => file,
             Err(err) => {
@@ -85,8 +229,8 @@ impl Storage for FileStorage {
             }
         };
 
-        let mut s = String::new();
-        match file.read_to_string(&mut s) {
+        let mut value = Vec::new();
+        match file.read_to_end(&mut value) {
             Err(err) => {
                 error!("Couldn't read {:?}: {}", path, err);
                 return Err(StorageError::ReadError(err.to_string()));
@@ -94,16 +238,71 @@ impl Storage for FileStorage {
             Ok(size) => debug!("Read {} ({} bytes) from {:?}", key, size, path),
         };
 
-        Ok(s.into_bytes())
+        Ok(value)
+    }
+
+    fn delete(&mut self, key: &str) -> Result<(), StorageError> {
+        let path = self._pathfor(key);
+        let _lock = self._lock(key, &path, true)?;
+        self._remove_stale_temps(&path).map_err(|err| {
+            error!("Couldn't delete temporary files of {:?}: {}", path, err);
+            StorageError::WriteError(err.to_string())
+        })?;
+        // The lock file goes last, while still held, so that waiting processes can notice it.
+        for path in &[path.clone(), self._lockpath(&path)] {
+            match fs::remove_file(path) {
+                Ok(()) => debug!("Deleted {} from {:?}", key, path),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => {
+                    error!("Couldn't delete {:?}: {}", path, err);
+                    return Err(StorageError::WriteError(err.to_string()));
+                }
+            }
+        }
+        sync_dir(&self.folder)?;
+        Ok(())
+    }
+
+    fn keys(&self, prefix: Option<&str>) -> Result<Vec<String>, StorageError> {
+        let prefix = self._slug(prefix.unwrap_or_default());
+        let entries = fs::read_dir(&self.folder).map_err(|err| {
+            error!("Couldn't list {:?}: {}", self.folder, err);
+            StorageError::ReadError(err.to_string())
+        })?;
+        let mut keys = Vec::new();
+        for entry in entries {
+            let path = entry
+                .map_err(|err| StorageError::ReadError(err.to_string()))?
+                .path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(self.extension.as_str()) {
+                continue;
+            }
+            if let Some(key) = path.file_stem().and_then(|stem| stem.to_str()) {
+                // Locks and temporary files start with a dot.
+                if !key.starts_with('.') && key.starts_with(&prefix) {
+                    keys.push(key.to_string());
+                }
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn canonical_key(&self, key: &str) -> String {
+        self._slug(key)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{FileStorage, Storage};
+    use super::{is_current_lock, FileStorage, Storage, StorageError};
     use env_logger;
+    use fs2::FileExt;
     use log::error;
-    use std::fs::remove_file;
+    use std::fs::File;
+    use std::fs::{create_dir, create_dir_all, read, read_dir, remove_dir_all, remove_file, write};
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
 
     fn init() {
         let _ = env_logger::builder().is_test(true).try_init();
@@ -113,6 +312,25 @@ mod tests {
         if remove_file(&file_path).is_err() {
             error!("Error removing file : {}", file_path);
         };
+        let path = Path::new(file_path);
+        let lock_name = format!(".{}.lock", path.file_name().unwrap().to_string_lossy());
+        let _ = remove_file(path.with_file_name(lock_name));
+    }
+
+    fn temp_folder(name: &str) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("rs-file-storage-{}", name));
+        let _ = remove_dir_all(&folder);
+        create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    fn list_files(folder: &PathBuf) -> Vec<String> {
+        let mut names: Vec<String> = read_dir(folder)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
     }
 
     #[test]
@@ -133,6 +351,24 @@ mod tests {
         cleanup("./store-key.bin");
     }
 
+    #[test]
+    fn test_store_binary_value() {
+        init();
+
+        let mut storage = FileStorage::default();
+        cleanup("./binary-key.bin");
+
+        storage
+            .store("binary-key", vec![0x01, 0x02, 0xff, 0x00, 0xfe])
+            .unwrap();
+
+        assert_eq!(
+            storage.retrieve("binary-key").unwrap(),
+            vec![0x01, 0x02, 0xff, 0x00, 0xfe]
+        );
+        cleanup("./binary-key.bin");
+    }
+
     #[test]
     fn test_store_overwrite_file() {
         init();
@@ -183,5 +419,177 @@ mod tests {
             .unwrap();
 
         remove_file("./a_bid+a-cid+Records.bin").unwrap(); // Fails if file is missing.
+
+        cleanup("./+etc+password.bin");
+        cleanup("./a_bid+a-cid+Records.bin");
+    }
+
+    #[test]
+    fn test_store_leaves_no_temporary_file() {
+        init();
+
+        let folder = temp_folder("no-temp");
+        let mut storage = FileStorage {
+            folder: folder.clone(),
+            ..FileStorage::default()
+        };
+
+        storage.store("key", b"value".to_vec()).unwrap();
+        storage.store("key", b"new value".to_vec()).unwrap();
+
+        assert_eq!(list_files(&folder), vec![".key.bin.lock", "key.bin"]);
+        remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_store_removes_temporary_files_of_interrupted_writes() {
+        init();
+
+        let folder = temp_folder("interrupted");
+        let mut storage = FileStorage {
+            folder: folder.clone(),
+            ..FileStorage::default()
+        };
+        storage.store("key", b"complete value".to_vec()).unwrap();
+
+        // Simulate a crash between the write of the temporary file and its rename.
+        write(folder.join(".key.bin.1234-0.tmp"), b"compl").unwrap();
+        write(folder.join(".other.bin.1234-1.tmp"), b"other").unwrap();
+
+        assert_eq!(storage.retrieve("key").unwrap(), b"complete value");
+        assert_eq!(storage.keys(None).unwrap(), vec!["key"]);
+
+        storage.store("key", b"next value".to_vec()).unwrap();
+        assert_eq!(storage.retrieve("key").unwrap(), b"next value");
+        assert_eq!(
+            list_files(&folder),
+            vec![".key.bin.lock", ".other.bin.1234-1.tmp", "key.bin"]
+        );
+        remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_failed_rename_keeps_previous_value() {
+        init();
+
+        let folder = temp_folder("failed");
+        let mut storage = FileStorage {
+            folder: folder.clone(),
+            ..FileStorage::default()
+        };
+        // The final rename fails, since a non-empty directory occupies the target path.
+        create_dir(folder.join("key.bin")).unwrap();
+        write(folder.join("key.bin").join("file"), b"previous").unwrap();
+        write(folder.join(".key.bin.1234-0.tmp"), b"compl").unwrap();
+
+        assert!(storage.store("key", b"value".to_vec()).is_err());
+
+        assert_eq!(list_files(&folder), vec![".key.bin.lock", "key.bin"]);
+        assert_eq!(
+            read(folder.join("key.bin").join("file")).unwrap(),
+            b"previous"
+        );
+        remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_lock_timeout() {
+        init();
+
+        let folder = temp_folder("lock-timeout");
+        let mut storage = FileStorage {
+            folder: folder.clone(),
+            lock_timeout: Duration::from_millis(50),
+            ..FileStorage::default()
+        };
+        storage.store("key", b"value".to_vec()).unwrap();
+
+        // Another process is reading.
+        let other = File::create(folder.join(".key.bin.lock")).unwrap();
+        FileExt::lock_shared(&other).unwrap();
+
+        assert_eq!(storage.retrieve("key").unwrap(), b"value");
+        assert_eq!(
+            storage.store("key", b"new value".to_vec()).unwrap_err(),
+            StorageError::LockTimeout {
+                key: "key".to_string()
+            }
+        );
+
+        // Another process is writing.
+        FileExt::unlock(&other).unwrap();
+        FileExt::lock_exclusive(&other).unwrap();
+
+        assert_eq!(
+            storage.retrieve("key").unwrap_err(),
+            StorageError::LockTimeout {
+                key: "key".to_string()
+            }
+        );
+
+        FileExt::unlock(&other).unwrap();
+        storage.store("key", b"new value".to_vec()).unwrap();
+        assert_eq!(storage.retrieve("key").unwrap(), b"new value");
+        remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_delete_keys_and_clear() {
+        init();
+
+        let folder = temp_folder("keys");
+        let mut storage = FileStorage {
+            folder: folder.clone(),
+            ..FileStorage::default()
+        };
+        storage.store("main/a", b"a".to_vec()).unwrap();
+        storage.store("main/b", b"b".to_vec()).unwrap();
+        storage.store("other", b"c".to_vec()).unwrap();
+        write(folder.join("unrelated.txt"), "").unwrap();
+        write(folder.join(".other.bin.1234-0.tmp"), "").unwrap();
+
+        assert_eq!(
+            storage.keys(None).unwrap(),
+            vec!["main+a", "main+b", "other"]
+        );
+        assert_eq!(
+            storage.keys(Some("main/")).unwrap(),
+            vec!["main+a", "main+b"]
+        );
+
+        storage.delete("main/a").unwrap();
+        storage.delete("unknown").unwrap();
+        assert_eq!(storage.keys(None).unwrap(), vec!["main+b", "other"]);
+        assert!(storage.retrieve("main/a").is_err());
+        assert!(!list_files(&folder).contains(&".main+a.bin.lock".to_string()));
+        assert!(!list_files(&folder).contains(&".unknown.bin.lock".to_string()));
+
+        storage.clear().unwrap();
+        assert!(storage.keys(None).unwrap().is_empty());
+        assert_eq!(list_files(&folder), vec!["unrelated.txt"]);
+        remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_lock_file_removed_while_waiting() {
+        init();
+
+        let folder = temp_folder("relock");
+        let storage = FileStorage {
+            folder: folder.clone(),
+            ..FileStorage::default()
+        };
+        let path = storage._pathfor("key");
+        let lock_path = storage._lockpath(&path);
+        let waiting = storage._lock("key", &path, true).unwrap();
+        assert!(is_current_lock(&waiting, &lock_path));
+
+        // A concurrent delete removes the lock file, and another process creates it again.
+        remove_file(&lock_path).unwrap();
+        assert!(!is_current_lock(&waiting, &lock_path));
+        let other = storage._lock("key", &path, true).unwrap();
+        assert!(is_current_lock(&other, &lock_path));
+        assert!(!is_current_lock(&waiting, &lock_path));
+        remove_dir_all(&folder).unwrap();
     }
 }
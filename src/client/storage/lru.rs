@@ -0,0 +1,90 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+/// Values with their size and last access, to evict the least recently used ones.
+pub(crate) struct Lru<V> {
+    entries: HashMap<String, Entry<V>>,
+    size: usize,
+    clock: u64,
+}
+
+struct Entry<V> {
+    value: V,
+    size: usize,
+    last_access: u64,
+}
+
+impl<V> Default for Lru<V> {
+    fn default() -> Self {
+        Lru {
+            entries: HashMap::new(),
+            size: 0,
+            clock: 0,
+        }
+    }
+}
+
+impl<V> Lru<V> {
+    /// Total size of the entries.
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Return the value of `key`, and mark it as recently used.
+    pub(crate) fn get(&mut self, key: &str) -> Option<&V> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.entries.get_mut(key).map(|entry| {
+            entry.last_access = clock;
+            &entry.value
+        })
+    }
+
+    /// Insert or replace the value of `key`, as the most recently used.
+    pub(crate) fn insert(&mut self, key: &str, value: V, size: usize) {
+        self.remove(key);
+        self.clock += 1;
+        self.size += size;
+        self.entries.insert(
+            key.to_string(),
+            Entry {
+                value,
+                size,
+                last_access: self.clock,
+            },
+        );
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) -> Option<V> {
+        self.entries.remove(key).map(|entry| {
+            self.size -= entry.size;
+            entry.value
+        })
+    }
+
+    /// Return the least recently used key, other than `except`.
+    pub(crate) fn least_recently_used(&self, except: Option<&str>) -> Option<String> {
+        self.entries
+            .iter()
+            .filter(|(key, _)| Some(key.as_str()) != except)
+            .min_by_key(|(_, entry)| entry.last_access)
+            .map(|(key, _)| key.clone())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.entries.keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+}
+
+/// Lock the mutex, even if a thread panicked while holding it: the values it
+/// protects are only updated in ways that leave them consistent.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
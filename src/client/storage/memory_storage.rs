@@ -32,4 +32,23 @@ impl Storage for MemoryStorage {
             }),
         }
     }
+
+    fn delete(&mut self, key: &str) -> Result<(), StorageError> {
+        self.mem.remove(key);
+        Ok(())
+    }
+
+    fn keys(&self, prefix: Option<&str>) -> Result<Vec<String>, StorageError> {
+        Ok(self
+            .mem
+            .keys()
+            .filter(|key| key.starts_with(prefix.unwrap_or_default()))
+            .cloned()
+            .collect())
+    }
+
+    fn clear(&mut self) -> Result<(), StorageError> {
+        self.mem.clear();
+        Ok(())
+    }
 }
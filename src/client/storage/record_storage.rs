@@ -0,0 +1,754 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use {
+    super::{CollectionStorage, Storage, StorageError},
+    crate::client::codec::{decode_collection, encode_collection, Codec},
+    crate::client::{Collection, KintoObject, Record},
+    log::{debug, warn},
+    serde_json::json,
+    std::collections::BTreeMap,
+};
+
+// Separates the collection key from the record id in the keys of record entries.
+// Only made of characters that every storage keeps as is in keys (eg. `FileStorage`).
+const RECORD_MARKER: &str = "--record--";
+
+/// Entry stored under the collection key: attributes of the collection and
+/// timestamps of its records, by id.
+#[derive(Debug, Default)]
+struct Index {
+    bid: String,
+    cid: String,
+    metadata: KintoObject,
+    timestamp: u64,
+    signer: String,
+    records: BTreeMap<String, u64>,
+}
+
+/// A storage wrapper where the records of collections are stored individually
+/// in another storage, so that synchronizations only write the changes.
+///
+/// The collection key holds a small index entry (metadata, timestamp, and record ids).
+/// Each version of a record is written under its own key, and the index is written
+/// last: if a write is interrupted, the previous version of the collection is kept.
+/// Versions no longer referenced by the index are then deleted.
+///
+/// Entries are serialized with the codec of the client, like whole collections (the index
+/// as a collection of record ids, each record as a collection of one record), so that they
+/// have the same header and checksum, and are migrated alike.
+///
+/// # Examples
+/// ```rust
+/// # use remote_settings_client::Client;
+/// # use remote_settings_client::client::{FileStorage, RecordStorage};
+/// # fn main() {
+/// let client = Client::builder()
+///   .collection_name("cid")
+///   .storage(Box::new(RecordStorage::new(Box::new(FileStorage::default()))))
+///   .build()
+///   .unwrap();
+/// # }
+/// ```
+pub struct RecordStorage {
+    inner: Box<dyn Storage>,
+}
+
+impl Index {
+    fn new(collection: &Collection, records: BTreeMap<String, u64>) -> Self {
+        Index {
+            bid: collection.bid.clone(),
+            cid: collection.cid.clone(),
+            metadata: collection.metadata.clone(),
+            timestamp: collection.timestamp,
+            signer: collection.signer.clone(),
+            records,
+        }
+    }
+
+    /// Stored as a collection whose records only have their id and timestamp.
+    fn to_collection(&self) -> Collection {
+        Collection {
+            bid: self.bid.clone(),
+            cid: self.cid.clone(),
+            metadata: self.metadata.clone(),
+            records: self
+                .records
+                .iter()
+                .map(|(id, last_modified)| {
+                    Record::new(json!({"id": id, "last_modified": last_modified}))
+                })
+                .collect(),
+            timestamp: self.timestamp,
+            signer: self.signer.clone(),
+        }
+    }
+
+    fn from_collection(collection: Collection) -> Self {
+        let records = collection
+            .records
+            .iter()
+            .map(|record| (record.id().to_string(), record.last_modified()))
+            .collect();
+        Index::new(&collection, records)
+    }
+}
+
+impl RecordStorage {
+    pub fn new(inner: Box<dyn Storage>) -> Self {
+        RecordStorage { inner }
+    }
+
+    fn record_key(key: &str, id: &str, last_modified: u64) -> String {
+        // Record ids are hex-encoded, since storages may not support any character in keys.
+        format!(
+            "{}{}{}-{}",
+            key,
+            RECORD_MARKER,
+            hex::encode(id),
+            last_modified
+        )
+    }
+
+    fn read_index(&self, key: &str, codec: &dyn Codec) -> Result<Index, StorageError> {
+        let bytes = self.inner.retrieve(key)?;
+        Ok(Index::from_collection(decode_collection(codec, &bytes)?))
+    }
+
+    fn read_record(
+        &self,
+        key: &str,
+        id: &str,
+        last_modified: u64,
+        codec: &dyn Codec,
+    ) -> Result<Record, StorageError> {
+        let bytes = self
+            .inner
+            .retrieve(&Self::record_key(key, id, last_modified))
+            .map_err(|err| match err {
+                StorageError::KeyNotFound { key } => {
+                    StorageError::ReadError(format!("missing record entry {}", key))
+                }
+                err => err,
+            })?;
+        decode_collection(codec, &bytes)?
+            .records
+            .pop()
+            .ok_or_else(|| StorageError::ReadError(format!("empty record entry for {}", id)))
+    }
+
+    /// Write the record, stored as a collection with only this record.
+    fn write_record(
+        &mut self,
+        key: &str,
+        record: &Record,
+        codec: &dyn Codec,
+    ) -> Result<(), StorageError> {
+        let entry = Collection {
+            bid: String::new(),
+            cid: String::new(),
+            metadata: KintoObject::Null,
+            records: vec![record.clone()],
+            timestamp: record.last_modified(),
+            signer: String::new(),
+        };
+        let bytes = encode_collection(codec, &entry)?;
+        self.inner.store(
+            &Self::record_key(key, record.id(), record.last_modified()),
+            bytes,
+        )
+    }
+
+    /// Write the index, and then delete the record entries that it no longer references.
+    fn commit(
+        &mut self,
+        key: &str,
+        previous: &Index,
+        index: &Index,
+        codec: &dyn Codec,
+    ) -> Result<(), StorageError> {
+        let bytes = encode_collection(codec, &index.to_collection())?;
+        self.inner.store(key, bytes)?;
+
+        for (id, last_modified) in &previous.records {
+            if index.records.get(id) != Some(last_modified) {
+                // The new index is already stored: leftovers are harmless.
+                if let Err(err) = self
+                    .inner
+                    .delete(&Self::record_key(key, id, *last_modified))
+                {
+                    warn!("Couldn't delete previous version of {}: {}", id, err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the index to be replaced. An index that cannot be read (eg. a collection
+    /// stored as a whole value, or corrupted) is overwritten as if there was none.
+    fn previous_index(&self, key: &str, codec: &dyn Codec) -> Result<Index, StorageError> {
+        match self.read_index(key, codec) {
+            Err(StorageError::KeyNotFound { .. }) => Ok(Index::default()),
+            Err(err @ StorageError::ReadError(_))
+            | Err(err @ StorageError::CorruptedData(_))
+            | Err(err @ StorageError::UnsupportedSchema(_)) => {
+                warn!("Overwrite unreadable index of {}: {}", key, err);
+                Ok(Index::default())
+            }
+            result => result,
+        }
+    }
+
+    /// Whether the entry of this version of the record can be kept, ie. it is referenced
+    /// by the previous index and still readable (eg. not evicted by the inner storage).
+    fn is_stored(&self, key: &str, previous: &Index, record: &Record, codec: &dyn Codec) -> bool {
+        previous.records.get(record.id()) == Some(&record.last_modified())
+            && self
+                .read_record(key, record.id(), record.last_modified(), codec)
+                .is_ok()
+    }
+}
+
+impl Storage for RecordStorage {
+    fn store(&mut self, key: &str, value: Vec<u8>) -> Result<(), StorageError> {
+        self.inner.store(key, value)
+    }
+
+    fn retrieve(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        self.inner.retrieve(key)
+    }
+
+    /// Delete the value of a given key, and the records if it is a collection.
+    ///
+    /// Record entries are found by listing the keys of the inner storage, so that
+    /// they are deleted whatever the codec of the index, or if it cannot be read.
+    fn delete(&mut self, key: &str) -> Result<(), StorageError> {
+        let prefix = format!("{}{}", key, RECORD_MARKER);
+        for record_key in self.inner.keys(Some(&prefix))? {
+            self.inner.delete(&record_key)?;
+        }
+        self.inner.delete(key)
+    }
+
+    /// List the stored keys, without the keys of record entries.
+    fn keys(&self, prefix: Option<&str>) -> Result<Vec<String>, StorageError> {
+        Ok(self
+            .inner
+            .keys(prefix)?
+            .into_iter()
+            .filter(|key| !key.contains(RECORD_MARKER))
+            .collect())
+    }
+
+    fn clear(&mut self) -> Result<(), StorageError> {
+        self.inner.clear()
+    }
+
+    fn canonical_key(&self, key: &str) -> String {
+        self.inner.canonical_key(key)
+    }
+
+    fn as_collection_storage(&self) -> Option<&dyn CollectionStorage> {
+        Some(self)
+    }
+
+    fn as_collection_storage_mut(&mut self) -> Option<&mut dyn CollectionStorage> {
+        Some(self)
+    }
+}
+
+impl CollectionStorage for RecordStorage {
+    fn store_collection(
+        &mut self,
+        key: &str,
+        collection: &Collection,
+        codec: &dyn Codec,
+    ) -> Result<(), StorageError> {
+        let previous = self.previous_index(key, codec)?;
+        let mut index = Index::new(collection, BTreeMap::new());
+        let mut written = 0;
+        for record in &collection.records {
+            // Versions of records are immutable, the stored ones can be kept.
+            if !self.is_stored(key, &previous, record, codec) {
+                self.write_record(key, record, codec)?;
+                written += 1;
+            }
+            index
+                .records
+                .insert(record.id().to_string(), record.last_modified());
+        }
+        self.commit(key, &previous, &index, codec)?;
+        debug!(
+            "Wrote {} ({} of {} records)",
+            key,
+            written,
+            collection.records.len()
+        );
+        Ok(())
+    }
+
+    fn retrieve_collection(
+        &self,
+        key: &str,
+        codec: &dyn Codec,
+    ) -> Result<Collection, StorageError> {
+        let index = self.read_index(key, codec)?;
+        let records = index
+            .records
+            .iter()
+            .map(|(id, last_modified)| self.read_record(key, id, *last_modified, codec))
+            .collect::<Result<Vec<Record>, StorageError>>()?;
+        debug!("Read {} ({} records)", key, records.len());
+
+        Ok(Collection {
+            bid: index.bid,
+            cid: index.cid,
+            metadata: index.metadata,
+            records,
+            timestamp: index.timestamp,
+            signer: index.signer,
+        })
+    }
+
+    fn apply_changes(
+        &mut self,
+        key: &str,
+        merged: &Collection,
+        changes: &[Record],
+        codec: &dyn Codec,
+    ) -> Result<(), StorageError> {
+        // The index is built from the merged records rather than from the previous index,
+        // which may be unreadable or reference evicted entries. Only the entries of the
+        // changed (or missing) records are written.
+        self.store_collection(key, merged, codec)?;
+        debug!("Applied {} changes to {}", changes.len(), key);
+        Ok(())
+    }
+
+    fn retrieve_record(
+        &self,
+        key: &str,
+        id: &str,
+        codec: &dyn Codec,
+    ) -> Result<Option<Record>, StorageError> {
+        let index = match self.read_index(key, codec) {
+            Err(StorageError::KeyNotFound { .. }) => return Ok(None),
+            result => result?,
+        };
+        index
+            .records
+            .get(id)
+            .map(|last_modified| self.read_record(key, id, *last_modified, codec))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RecordStorage;
+    use crate::client::codec::JsonCodec;
+    use crate::client::storage::{memory_storage::MemoryStorage, Storage, StorageError};
+    use crate::client::testing::test_collection;
+    use crate::client::Record;
+    use serde_json::json;
+
+    fn stored_keys(storage: &RecordStorage) -> Vec<String> {
+        let mut keys = storage.inner.keys(None).unwrap();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn test_store_and_retrieve_collection() {
+        let mut storage = RecordStorage::new(Box::new(MemoryStorage::new()));
+        let records = storage.as_collection_storage_mut().unwrap();
+
+        records
+            .store_collection(
+                "main/cfr",
+                &test_collection(
+                    "cfr",
+                    10,
+                    vec![
+                        Record::new(json!({"id": "b/1", "last_modified": 10})),
+                        Record::new(json!({"id": "a", "last_modified": 9})),
+                    ],
+                ),
+                &JsonCodec {},
+            )
+            .unwrap();
+
+        assert_eq!(
+            records
+                .retrieve_collection("main/cfr", &JsonCodec {})
+                .unwrap(),
+            test_collection(
+                "cfr",
+                10,
+                vec![
+                    Record::new(json!({"id": "a", "last_modified": 9})),
+                    Record::new(json!({"id": "b/1", "last_modified": 10})),
+                ]
+            )
+        );
+        assert_eq!(
+            stored_keys(&storage),
+            vec![
+                "main/cfr",
+                "main/cfr--record--61-9",
+                "main/cfr--record--622f31-10"
+            ]
+        );
+        assert_eq!(storage.keys(None).unwrap(), vec!["main/cfr"]);
+        assert!(matches!(
+            storage
+                .as_collection_storage()
+                .unwrap()
+                .retrieve_collection("main/unknown", &JsonCodec {}),
+            Err(StorageError::KeyNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        let mut storage = RecordStorage::new(Box::new(MemoryStorage::new()));
+        let records = storage.as_collection_storage_mut().unwrap();
+        records
+            .store_collection(
+                "main/cfr",
+                &test_collection(
+                    "cfr",
+                    10,
+                    vec![
+                        Record::new(json!({"id": "a", "last_modified": 9})),
+                        Record::new(json!({"id": "b", "last_modified": 10})),
+                        Record::new(json!({"id": "c", "last_modified": 10})),
+                    ],
+                ),
+                &JsonCodec {},
+            )
+            .unwrap();
+
+        records
+            .apply_changes(
+                "main/cfr",
+                &test_collection(
+                    "cfr",
+                    12,
+                    vec![
+                        Record::new(json!({"id": "b", "last_modified": 11, "foo": "bar"})),
+                        Record::new(json!({"id": "c", "last_modified": 10})),
+                        Record::new(json!({"id": "d", "last_modified": 11})),
+                    ],
+                ),
+                &[
+                    Record::new(json!({"id": "a", "last_modified": 12, "deleted": true})),
+                    Record::new(json!({"id": "b", "last_modified": 11, "foo": "bar"})),
+                    Record::new(json!({"id": "d", "last_modified": 11})),
+                ],
+                &JsonCodec {},
+            )
+            .unwrap();
+
+        let stored = records
+            .retrieve_collection("main/cfr", &JsonCodec {})
+            .unwrap();
+        assert_eq!(stored.timestamp, 12);
+        assert_eq!(
+            stored.records,
+            vec![
+                Record::new(json!({"id": "b", "last_modified": 11, "foo": "bar"})),
+                Record::new(json!({"id": "c", "last_modified": 10})),
+                Record::new(json!({"id": "d", "last_modified": 11})),
+            ]
+        );
+        assert_eq!(
+            records
+                .retrieve_record("main/cfr", "a", &JsonCodec {})
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            records
+                .retrieve_record("main/cfr", "b", &JsonCodec {})
+                .unwrap()
+                .unwrap()["foo"]
+                .as_str(),
+            Some("bar")
+        );
+        // Previous versions were deleted.
+        assert_eq!(
+            stored_keys(&storage),
+            vec![
+                "main/cfr",
+                "main/cfr--record--62-11",
+                "main/cfr--record--63-10",
+                "main/cfr--record--64-11"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apply_changes_over_unreadable_index() {
+        let mut storage = RecordStorage::new(Box::new(MemoryStorage::new()));
+        storage
+            .store("main/cfr", b"{\"records\": []}".to_vec())
+            .unwrap();
+        let merged = test_collection(
+            "cfr",
+            11,
+            vec![
+                Record::new(json!({"id": "a", "last_modified": 10})),
+                Record::new(json!({"id": "b", "last_modified": 11})),
+            ],
+        );
+
+        let records = storage.as_collection_storage_mut().unwrap();
+        records
+            .apply_changes(
+                "main/cfr",
+                &merged,
+                &[Record::new(json!({"id": "b", "last_modified": 11}))],
+                &JsonCodec {},
+            )
+            .unwrap();
+
+        assert_eq!(
+            records
+                .retrieve_collection("main/cfr", &JsonCodec {})
+                .unwrap(),
+            merged
+        );
+    }
+
+    #[test]
+    fn test_apply_changes_rewrites_missing_entries() {
+        let mut storage = RecordStorage::new(Box::new(MemoryStorage::new()));
+        storage
+            .as_collection_storage_mut()
+            .unwrap()
+            .store_collection(
+                "main/cfr",
+                &test_collection(
+                    "cfr",
+                    10,
+                    vec![Record::new(json!({"id": "a", "last_modified": 10}))],
+                ),
+                &JsonCodec {},
+            )
+            .unwrap();
+        // The entry was evicted (eg. by a bounded storage).
+        storage.inner.delete("main/cfr--record--61-10").unwrap();
+        let merged = test_collection(
+            "cfr",
+            11,
+            vec![
+                Record::new(json!({"id": "a", "last_modified": 10})),
+                Record::new(json!({"id": "b", "last_modified": 11})),
+            ],
+        );
+
+        let records = storage.as_collection_storage_mut().unwrap();
+        records
+            .apply_changes(
+                "main/cfr",
+                &merged,
+                &[Record::new(json!({"id": "b", "last_modified": 11}))],
+                &JsonCodec {},
+            )
+            .unwrap();
+
+        assert_eq!(
+            records
+                .retrieve_collection("main/cfr", &JsonCodec {})
+                .unwrap(),
+            merged
+        );
+    }
+
+    #[test]
+    fn test_interrupted_write_keeps_previous_version() {
+        let mut storage = RecordStorage::new(Box::new(MemoryStorage::new()));
+        let records = storage.as_collection_storage_mut().unwrap();
+        records
+            .store_collection(
+                "main/cfr",
+                &test_collection(
+                    "cfr",
+                    10,
+                    vec![Record::new(json!({"id": "a", "last_modified": 10}))],
+                ),
+                &JsonCodec {},
+            )
+            .unwrap();
+
+        // The new version of a record was written, but not the index.
+        storage
+            .write_record(
+                "main/cfr",
+                &Record::new(json!({"id": "a", "last_modified": 11, "foo": "bar"})),
+                &JsonCodec {},
+            )
+            .unwrap();
+
+        let stored = storage
+            .as_collection_storage()
+            .unwrap()
+            .retrieve_collection("main/cfr", &JsonCodec {})
+            .unwrap();
+        assert_eq!(
+            stored,
+            test_collection(
+                "cfr",
+                10,
+                vec![Record::new(json!({"id": "a", "last_modified": 10}))]
+            )
+        );
+    }
+
+    #[test]
+    fn test_delete_collection() {
+        let mut storage = RecordStorage::new(Box::new(MemoryStorage::new()));
+        storage.store("other", b"value".to_vec()).unwrap();
+        storage
+            .as_collection_storage_mut()
+            .unwrap()
+            .store_collection(
+                "main/cfr",
+                &test_collection(
+                    "cfr",
+                    10,
+                    vec![Record::new(json!({"id": "a", "last_modified": 10}))],
+                ),
+                &JsonCodec {},
+            )
+            .unwrap();
+
+        storage.delete("main/cfr").unwrap();
+
+        assert_eq!(stored_keys(&storage), vec!["other"]);
+    }
+
+    #[test]
+    fn test_delete_collection_with_unreadable_index() {
+        let mut storage = RecordStorage::new(Box::new(MemoryStorage::new()));
+        storage
+            .as_collection_storage_mut()
+            .unwrap()
+            .store_collection(
+                "main/cfr",
+                &test_collection(
+                    "cfr",
+                    10,
+                    vec![Record::new(json!({"id": "a", "last_modified": 10}))],
+                ),
+                &JsonCodec {},
+            )
+            .unwrap();
+        // Eg. encoded with a custom codec.
+        storage
+            .inner
+            .store("main/cfr", b"unreadable".to_vec())
+            .unwrap();
+
+        storage.delete("main/cfr").unwrap();
+
+        assert!(stored_keys(&storage).is_empty());
+    }
+
+    #[test]
+    fn test_store_collection_over_unreadable_index() {
+        let mut storage = RecordStorage::new(Box::new(MemoryStorage::new()));
+        storage
+            .store("main/cfr", b"{\"records\": []}".to_vec())
+            .unwrap();
+        let collection = test_collection(
+            "cfr",
+            10,
+            vec![Record::new(json!({"id": "a", "last_modified": 10}))],
+        );
+
+        let records = storage.as_collection_storage_mut().unwrap();
+        records
+            .store_collection("main/cfr", &collection, &JsonCodec {})
+            .unwrap();
+
+        assert_eq!(
+            records
+                .retrieve_collection("main/cfr", &JsonCodec {})
+                .unwrap(),
+            collection
+        );
+    }
+
+    #[test]
+    fn test_store_collection_rewrites_missing_entries() {
+        let mut storage = RecordStorage::new(Box::new(MemoryStorage::new()));
+        let collection = test_collection(
+            "cfr",
+            10,
+            vec![
+                Record::new(json!({"id": "a", "last_modified": 9})),
+                Record::new(json!({"id": "b", "last_modified": 10})),
+            ],
+        );
+        storage
+            .as_collection_storage_mut()
+            .unwrap()
+            .store_collection("main/cfr", &collection, &JsonCodec {})
+            .unwrap();
+        // The entry was evicted (eg. by a bounded storage).
+        storage.inner.delete("main/cfr--record--61-9").unwrap();
+        let records = storage.as_collection_storage_mut().unwrap();
+        assert!(matches!(
+            records.retrieve_collection("main/cfr", &JsonCodec {}),
+            Err(StorageError::ReadError(_))
+        ));
+
+        records
+            .store_collection("main/cfr", &collection, &JsonCodec {})
+            .unwrap();
+
+        assert_eq!(
+            records
+                .retrieve_collection("main/cfr", &JsonCodec {})
+                .unwrap(),
+            collection
+        );
+    }
+
+    #[test]
+    fn test_entries_are_encoded_with_checksum() {
+        let mut storage = RecordStorage::new(Box::new(MemoryStorage::new()));
+        let collection = test_collection(
+            "cfr",
+            10,
+            vec![Record::new(json!({"id": "a", "last_modified": 10}))],
+        );
+        storage
+            .as_collection_storage_mut()
+            .unwrap()
+            .store_collection("main/cfr", &collection, &JsonCodec {})
+            .unwrap();
+
+        let index = storage.inner.retrieve("main/cfr").unwrap();
+        let mut entry = storage.inner.retrieve("main/cfr--record--61-10").unwrap();
+        assert_eq!(&index[..3], b"\x03\x01\x01");
+        assert_eq!(&entry[..3], b"\x03\x01\x01");
+
+        let last = entry.len() - 1;
+        entry[last] ^= 1;
+        storage
+            .inner
+            .store("main/cfr--record--61-10", entry)
+            .unwrap();
+        assert!(matches!(
+            storage
+                .as_collection_storage()
+                .unwrap()
+                .retrieve_collection("main/cfr", &JsonCodec {}),
+            Err(StorageError::CorruptedData(_))
+        ));
+    }
+}
@@ -0,0 +1,477 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use {
+    super::{CollectionStorage, Storage, StorageError},
+    crate::client::{Codec, Collection, Record},
+    log::debug,
+    rusqlite::{params, Connection, OptionalExtension, Transaction},
+    std::path::Path,
+};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS kv (
+        key TEXT PRIMARY KEY,
+        value BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS collections (
+        key TEXT PRIMARY KEY,
+        bid TEXT NOT NULL,
+        cid TEXT NOT NULL,
+        metadata TEXT NOT NULL,
+        timestamp INTEGER NOT NULL,
+        signer TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS records (
+        collection TEXT NOT NULL,
+        id TEXT NOT NULL,
+        data TEXT NOT NULL,
+        PRIMARY KEY (collection, id)
+    );
+";
+
+/// A storage backed by a SQLite database, where collections records are stored in a table.
+///
+/// Records are stored as JSON in their table, regardless of the codec of the client, and
+/// without checksum: corruption is only detected by SQLite itself (and reported as a read error).
+///
+/// With the `sqlite_storage` feature.
+///
+/// # Examples
+/// ```rust
+/// # use remote_settings_client::Client;
+/// # use remote_settings_client::client::SqliteStorage;
+/// # fn main() {
+/// let client = Client::builder()
+///   .collection_name("cid")
+///   .storage(Box::new(SqliteStorage::open("remote-settings.sqlite").unwrap()))
+///   .build()
+///   .unwrap();
+/// # std::fs::remove_file("remote-settings.sqlite").unwrap();
+/// # }
+/// ```
+pub struct SqliteStorage {
+    conn: Connection,
+}
+
+impl SqliteStorage {
+    /// Open (or create) the database at the specified path.
+    ///
+    /// # Errors
+    /// If the database cannot be opened or initialized, a [`StorageError::ReadError`] is returned.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, StorageError> {
+        Self::init(Connection::open(path).map_err(read_error)?)
+    }
+
+    /// Create a database in memory, mostly useful for tests.
+    pub fn open_in_memory() -> Result<Self, StorageError> {
+        Self::init(Connection::open_in_memory().map_err(read_error)?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, StorageError> {
+        conn.execute_batch(SCHEMA).map_err(read_error)?;
+        Ok(SqliteStorage { conn })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn store(&mut self, key: &str, value: Vec<u8>) -> Result<(), StorageError> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO kv (key, value) VALUES (?1, ?2)",
+                params![key, value],
+            )
+            .map_err(write_error)?;
+        debug!("Wrote {} ({} bytes)", key, value.len());
+        Ok(())
+    }
+
+    fn retrieve(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        self.conn
+            .query_row("SELECT value FROM kv WHERE key = ?1", params![key], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(read_error)?
+            .ok_or_else(|| StorageError::KeyNotFound {
+                key: key.to_string(),
+            })
+    }
+
+    fn delete(&mut self, key: &str) -> Result<(), StorageError> {
+        let tx = self.conn.transaction().map_err(write_error)?;
+        tx.execute("DELETE FROM kv WHERE key = ?1", params![key])
+            .map_err(write_error)?;
+        tx.execute("DELETE FROM collections WHERE key = ?1", params![key])
+            .map_err(write_error)?;
+        tx.execute("DELETE FROM records WHERE collection = ?1", params![key])
+            .map_err(write_error)?;
+        tx.commit().map_err(write_error)?;
+        debug!("Deleted {}", key);
+        Ok(())
+    }
+
+    fn keys(&self, prefix: Option<&str>) -> Result<Vec<String>, StorageError> {
+        // Keys of both values and collections, filtered in Rust to avoid escaping the LIKE pattern.
+        let mut statement = self
+            .conn
+            .prepare("SELECT key FROM kv UNION SELECT key FROM collections ORDER BY key")
+            .map_err(read_error)?;
+        let keys = statement
+            .query_map(params![], |row| row.get::<_, String>(0))
+            .map_err(read_error)?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(read_error)?;
+        Ok(keys
+            .into_iter()
+            .filter(|key| key.starts_with(prefix.unwrap_or_default()))
+            .collect())
+    }
+
+    fn clear(&mut self) -> Result<(), StorageError> {
+        self.conn
+            .execute_batch(
+                "BEGIN; DELETE FROM kv; DELETE FROM collections; DELETE FROM records; COMMIT;",
+            )
+            .map_err(write_error)?;
+        debug!("Cleared all keys");
+        Ok(())
+    }
+
+    fn as_collection_storage(&self) -> Option<&dyn CollectionStorage> {
+        Some(self)
+    }
+
+    fn as_collection_storage_mut(&mut self) -> Option<&mut dyn CollectionStorage> {
+        Some(self)
+    }
+}
+
+impl CollectionStorage for SqliteStorage {
+    fn store_collection(
+        &mut self,
+        key: &str,
+        collection: &Collection,
+        _codec: &dyn Codec,
+    ) -> Result<(), StorageError> {
+        let tx = self.conn.transaction().map_err(write_error)?;
+        tx.execute("DELETE FROM records WHERE collection = ?1", params![key])
+            .map_err(write_error)?;
+        upsert_collection(&tx, key, collection, &collection.records)?;
+        tx.commit().map_err(write_error)?;
+        debug!("Wrote {} ({} records)", key, collection.records.len());
+        Ok(())
+    }
+
+    fn retrieve_collection(
+        &self,
+        key: &str,
+        _codec: &dyn Codec,
+    ) -> Result<Collection, StorageError> {
+        let attributes = self
+            .conn
+            .query_row(
+                "SELECT bid, cid, metadata, timestamp, signer FROM collections WHERE key = ?1",
+                params![key],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, i64>(3)?,
+                        row.get::<_, String>(4)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(read_error)?;
+
+        let (bid, cid, metadata, timestamp, signer) =
+            attributes.ok_or_else(|| StorageError::KeyNotFound {
+                key: key.to_string(),
+            })?;
+
+        let mut statement = self
+            .conn
+            .prepare("SELECT data FROM records WHERE collection = ?1 ORDER BY id")
+            .map_err(read_error)?;
+        let records = statement
+            .query_map(params![key], |row| row.get::<_, String>(0))
+            .map_err(read_error)?
+            .map(|data| parse_json(&data.map_err(read_error)?))
+            .collect::<Result<Vec<Record>, StorageError>>()?;
+        debug!("Read {} ({} records)", key, records.len());
+
+        Ok(Collection {
+            bid,
+            cid,
+            metadata: parse_json(&metadata)?,
+            records,
+            timestamp: timestamp as u64,
+            signer,
+        })
+    }
+
+    fn apply_changes(
+        &mut self,
+        key: &str,
+        merged: &Collection,
+        changes: &[Record],
+        _codec: &dyn Codec,
+    ) -> Result<(), StorageError> {
+        let tx = self.conn.transaction().map_err(write_error)?;
+        upsert_collection(&tx, key, merged, changes)?;
+        tx.commit().map_err(write_error)?;
+        debug!("Applied {} changes to {}", changes.len(), key);
+        Ok(())
+    }
+
+    fn retrieve_record(
+        &self,
+        key: &str,
+        id: &str,
+        _codec: &dyn Codec,
+    ) -> Result<Option<Record>, StorageError> {
+        let data: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT data FROM records WHERE collection = ?1 AND id = ?2",
+                params![key, id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(read_error)?;
+        data.map(|data| parse_json(&data)).transpose()
+    }
+}
+
+/// Write the collection attributes and apply the records changes, within the transaction.
+fn upsert_collection(
+    tx: &Transaction,
+    key: &str,
+    collection: &Collection,
+    changes: &[Record],
+) -> Result<(), StorageError> {
+    tx.execute(
+        "INSERT OR REPLACE INTO collections (key, bid, cid, metadata, timestamp, signer)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            key,
+            collection.bid,
+            collection.cid,
+            collection.metadata.to_string(),
+            collection.timestamp as i64,
+            collection.signer
+        ],
+    )
+    .map_err(write_error)?;
+
+    for record in changes {
+        if record.deleted() {
+            tx.execute(
+                "DELETE FROM records WHERE collection = ?1 AND id = ?2",
+                params![key, record.id()],
+            )
+        } else {
+            let data = serde_json::to_string(record)
+                .map_err(|err| StorageError::WriteError(err.to_string()))?;
+            tx.execute(
+                "INSERT OR REPLACE INTO records (collection, id, data) VALUES (?1, ?2, ?3)",
+                params![key, record.id(), data],
+            )
+        }
+        .map_err(write_error)?;
+    }
+    Ok(())
+}
+
+fn parse_json<T: serde::de::DeserializeOwned>(data: &str) -> Result<T, StorageError> {
+    serde_json::from_str(data).map_err(|err| StorageError::ReadError(err.to_string()))
+}
+
+fn read_error(err: rusqlite::Error) -> StorageError {
+    StorageError::ReadError(err.to_string())
+}
+
+fn write_error(err: rusqlite::Error) -> StorageError {
+    StorageError::WriteError(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SqliteStorage;
+    use crate::client::codec::JsonCodec;
+    use crate::client::storage::{Storage, StorageError};
+    use crate::client::testing::test_collection;
+    use crate::client::Record;
+    use serde_json::json;
+
+    #[test]
+    fn test_store_and_retrieve_values() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+
+        storage.store("key", vec![0, 159, 146, 150]).unwrap();
+        storage.store("key", vec![1, 2]).unwrap();
+
+        assert_eq!(storage.retrieve("key").unwrap(), vec![1, 2]);
+        assert_eq!(
+            storage.retrieve("unknown").unwrap_err(),
+            StorageError::KeyNotFound {
+                key: "unknown".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn test_store_and_retrieve_collection() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+        let records = storage.as_collection_storage_mut().unwrap();
+
+        records
+            .store_collection(
+                "main/cfr",
+                &test_collection(
+                    "cfr",
+                    10,
+                    vec![
+                        Record::new(json!({"id": "b", "last_modified": 10})),
+                        Record::new(json!({"id": "a", "last_modified": 9})),
+                    ],
+                ),
+                &JsonCodec {},
+            )
+            .unwrap();
+        records
+            .store_collection(
+                "main/cfr",
+                &test_collection(
+                    "cfr",
+                    11,
+                    vec![Record::new(json!({"id": "c", "last_modified": 11}))],
+                ),
+                &JsonCodec {},
+            )
+            .unwrap();
+
+        let stored = records
+            .retrieve_collection("main/cfr", &JsonCodec {})
+            .unwrap();
+        assert_eq!(
+            stored,
+            test_collection(
+                "cfr",
+                11,
+                vec![Record::new(json!({"id": "c", "last_modified": 11}))]
+            )
+        );
+        assert!(records
+            .retrieve_collection("main/unknown", &JsonCodec {})
+            .is_err());
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+        let records = storage.as_collection_storage_mut().unwrap();
+        records
+            .store_collection(
+                "main/cfr",
+                &test_collection(
+                    "cfr",
+                    10,
+                    vec![
+                        Record::new(json!({"id": "a", "last_modified": 9})),
+                        Record::new(json!({"id": "b", "last_modified": 10})),
+                    ],
+                ),
+                &JsonCodec {},
+            )
+            .unwrap();
+
+        records
+            .apply_changes(
+                "main/cfr",
+                &test_collection("cfr", 12, vec![]),
+                &[
+                    Record::new(json!({"id": "a", "last_modified": 12, "deleted": true})),
+                    Record::new(json!({"id": "b", "last_modified": 11, "foo": "bar"})),
+                    Record::new(json!({"id": "c", "last_modified": 11})),
+                ],
+                &JsonCodec {},
+            )
+            .unwrap();
+
+        let stored = records
+            .retrieve_collection("main/cfr", &JsonCodec {})
+            .unwrap();
+        assert_eq!(stored.timestamp, 12);
+        assert_eq!(
+            stored.records,
+            vec![
+                Record::new(json!({"id": "b", "last_modified": 11, "foo": "bar"})),
+                Record::new(json!({"id": "c", "last_modified": 11})),
+            ]
+        );
+        assert_eq!(
+            records
+                .retrieve_record("main/cfr", "a", &JsonCodec {})
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            records
+                .retrieve_record("main/cfr", "b", &JsonCodec {})
+                .unwrap()
+                .unwrap()["foo"]
+                .as_str(),
+            Some("bar")
+        );
+    }
+
+    #[test]
+    fn test_delete_keys_and_clear() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+        storage.store("main/a", vec![1]).unwrap();
+        storage.store("other/b", vec![2]).unwrap();
+        storage
+            .as_collection_storage_mut()
+            .unwrap()
+            .store_collection(
+                "main/cfr",
+                &test_collection(
+                    "cfr",
+                    10,
+                    vec![Record::new(json!({"id": "a", "last_modified": 9}))],
+                ),
+                &JsonCodec {},
+            )
+            .unwrap();
+
+        assert_eq!(
+            storage.keys(None).unwrap(),
+            vec!["main/a", "main/cfr", "other/b"]
+        );
+        assert_eq!(
+            storage.keys(Some("main/")).unwrap(),
+            vec!["main/a", "main/cfr"]
+        );
+
+        storage.delete("main/cfr").unwrap();
+        storage.delete("unknown").unwrap();
+        assert_eq!(storage.keys(None).unwrap(), vec!["main/a", "other/b"]);
+        let records = storage.as_collection_storage().unwrap();
+        assert!(records
+            .retrieve_collection("main/cfr", &JsonCodec {})
+            .is_err());
+        assert_eq!(
+            records
+                .retrieve_record("main/cfr", "a", &JsonCodec {})
+                .unwrap(),
+            None
+        );
+
+        storage.clear().unwrap();
+        assert!(storage.keys(None).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,348 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use {
+    super::lru::{lock, Lru},
+    super::{CollectionStorage, Storage, StorageError},
+    crate::client::codec::{decode_collection, encode_collection, Codec},
+    crate::client::{Collection, Record},
+    log::debug,
+    std::sync::{Mutex, MutexGuard},
+};
+
+const DEFAULT_MEMORY_BUDGET: usize = 10 * 1024 * 1024;
+
+/// Insert the deserialized collection, with its (estimated) size, and evict the least
+/// recently used collections until it fits in the budget.
+fn insert_within_budget(
+    cache: &mut Lru<Collection>,
+    key: &str,
+    collection: Collection,
+    size: usize,
+    budget: usize,
+) {
+    cache.remove(key);
+    if size > budget {
+        debug!("{} does not fit in the memory budget ({} bytes)", key, size);
+        return;
+    }
+    while cache.size() + size > budget {
+        let lru = cache.least_recently_used(None).unwrap(); // Not empty, since size > 0.
+        debug!("Evict {} from memory", lru);
+        cache.remove(&lru);
+    }
+    cache.insert(key, collection, size);
+}
+
+/// A storage which keeps the deserialized collections in memory, and writes through
+/// to another (persistent) storage.
+///
+/// Collections are read from the backend once, and then served from memory until they
+/// are updated by a synchronization. The least recently used collections are evicted
+/// from memory when the budget is exceeded (their size is estimated from their serialized form).
+/// Collections are written to the backend with the codec of the client.
+///
+/// # Examples
+/// ```rust
+/// # use remote_settings_client::Client;
+/// # use remote_settings_client::client::{FileStorage, TieredStorage};
+/// # fn main() {
+/// let client = Client::builder()
+///   .collection_name("cid")
+///   .storage(Box::new(
+///     TieredStorage::new(Box::new(FileStorage::default())).memory_budget(1024 * 1024),
+///   ))
+///   .build()
+///   .unwrap();
+/// # }
+/// ```
+pub struct TieredStorage {
+    backend: Box<dyn Storage>,
+    memory_budget: usize,
+    cache: Mutex<Lru<Collection>>,
+}
+
+impl TieredStorage {
+    pub fn new(backend: Box<dyn Storage>) -> Self {
+        TieredStorage {
+            backend,
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            cache: Mutex::new(Lru::default()),
+        }
+    }
+
+    /// Maximum size of the collections kept in memory, in bytes (default: 10MB).
+    pub fn memory_budget(mut self, memory_budget: usize) -> Self {
+        self.memory_budget = memory_budget;
+        self
+    }
+
+    fn cache(&self) -> MutexGuard<'_, Lru<Collection>> {
+        lock(&self.cache)
+    }
+
+    /// Write the collection to the backend, and return its size.
+    fn write_through(
+        &mut self,
+        key: &str,
+        collection: &Collection,
+        changes: Option<&[Record]>,
+        codec: &dyn Codec,
+    ) -> Result<usize, StorageError> {
+        if let Some(backend) = self.backend.as_collection_storage_mut() {
+            match changes {
+                Some(changes) => backend.apply_changes(key, collection, changes, codec)?,
+                None => backend.store_collection(key, collection, codec)?,
+            }
+            return Ok(estimate_size(collection));
+        }
+        let bytes = encode_collection(codec, collection)?;
+        let size = bytes.len();
+        self.backend.store(key, bytes)?;
+        Ok(size)
+    }
+}
+
+fn estimate_size(collection: &Collection) -> usize {
+    serde_json::to_vec(collection)
+        .map(|bytes| bytes.len())
+        .unwrap_or(usize::MAX)
+}
+
+impl Storage for TieredStorage {
+    fn store(&mut self, key: &str, value: Vec<u8>) -> Result<(), StorageError> {
+        self.cache().remove(key);
+        self.backend.store(key, value)
+    }
+
+    fn retrieve(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        self.backend.retrieve(key)
+    }
+
+    fn delete(&mut self, key: &str) -> Result<(), StorageError> {
+        self.cache().remove(key);
+        self.backend.delete(key)
+    }
+
+    fn keys(&self, prefix: Option<&str>) -> Result<Vec<String>, StorageError> {
+        self.backend.keys(prefix)
+    }
+
+    fn clear(&mut self) -> Result<(), StorageError> {
+        *self.cache() = Lru::default();
+        self.backend.clear()
+    }
+
+    fn canonical_key(&self, key: &str) -> String {
+        self.backend.canonical_key(key)
+    }
+
+    fn as_collection_storage(&self) -> Option<&dyn CollectionStorage> {
+        Some(self)
+    }
+
+    fn as_collection_storage_mut(&mut self) -> Option<&mut dyn CollectionStorage> {
+        Some(self)
+    }
+}
+
+impl CollectionStorage for TieredStorage {
+    fn store_collection(
+        &mut self,
+        key: &str,
+        collection: &Collection,
+        codec: &dyn Codec,
+    ) -> Result<(), StorageError> {
+        self.cache().remove(key);
+        let size = self.write_through(key, collection, None, codec)?;
+        let budget = self.memory_budget;
+        insert_within_budget(&mut self.cache(), key, collection.clone(), size, budget);
+        Ok(())
+    }
+
+    fn retrieve_collection(
+        &self,
+        key: &str,
+        codec: &dyn Codec,
+    ) -> Result<Collection, StorageError> {
+        if let Some(collection) = self.cache().get(key).cloned() {
+            debug!("Read {} from memory", key);
+            return Ok(collection);
+        }
+        let (collection, size) = match self.backend.as_collection_storage() {
+            Some(backend) => {
+                let collection = backend.retrieve_collection(key, codec)?;
+                let size = estimate_size(&collection);
+                (collection, size)
+            }
+            None => {
+                let bytes = self.backend.retrieve(key)?;
+                (decode_collection(codec, &bytes)?, bytes.len())
+            }
+        };
+        insert_within_budget(
+            &mut self.cache(),
+            key,
+            collection.clone(),
+            size,
+            self.memory_budget,
+        );
+        Ok(collection)
+    }
+
+    fn apply_changes(
+        &mut self,
+        key: &str,
+        merged: &Collection,
+        changes: &[Record],
+        codec: &dyn Codec,
+    ) -> Result<(), StorageError> {
+        self.cache().remove(key);
+        let size = self.write_through(key, merged, Some(changes), codec)?;
+        let budget = self.memory_budget;
+        insert_within_budget(&mut self.cache(), key, merged.clone(), size, budget);
+        Ok(())
+    }
+
+    fn retrieve_record(
+        &self,
+        key: &str,
+        id: &str,
+        codec: &dyn Codec,
+    ) -> Result<Option<Record>, StorageError> {
+        match self.retrieve_collection(key, codec) {
+            Ok(collection) => Ok(collection.records.into_iter().find(|r| r.id() == id)),
+            Err(StorageError::KeyNotFound { .. }) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TieredStorage;
+    use crate::client::codec::{decode_collection, encode_collection, JsonCodec};
+    use crate::client::storage::{
+        memory_storage::MemoryStorage, record_storage::RecordStorage, CollectionStorage, Storage,
+    };
+    use crate::client::testing::test_collection;
+    use crate::client::{Collection, Record};
+    use serde_json::json;
+
+    fn collection(cid: &str, timestamp: u64) -> Collection {
+        let records = vec![Record::new(json!({"id": "a", "last_modified": timestamp}))];
+        test_collection(cid, timestamp, records)
+    }
+
+    fn cached_keys(storage: &TieredStorage) -> Vec<String> {
+        storage.cache().keys()
+    }
+
+    #[test]
+    fn test_collections_are_read_from_memory() {
+        let mut storage = TieredStorage::new(Box::new(MemoryStorage::new()));
+        storage
+            .store_collection("cfr", &collection("cfr", 10), &JsonCodec {})
+            .unwrap();
+
+        // Written through to the backend.
+        let stored = storage.backend.retrieve("cfr").unwrap();
+        assert_eq!(
+            decode_collection(&JsonCodec {}, &stored).unwrap(),
+            collection("cfr", 10)
+        );
+
+        // Served from memory, even if the backend changed.
+        storage.backend.store("cfr", b"garbage".to_vec()).unwrap();
+        assert_eq!(
+            storage.retrieve_collection("cfr", &JsonCodec {}).unwrap(),
+            collection("cfr", 10)
+        );
+
+        // Updates invalidate the previous version.
+        storage
+            .apply_changes("cfr", &collection("cfr", 11), &[], &JsonCodec {})
+            .unwrap();
+        assert_eq!(
+            storage
+                .retrieve_collection("cfr", &JsonCodec {})
+                .unwrap()
+                .timestamp,
+            11
+        );
+        storage.store("cfr", b"garbage".to_vec()).unwrap();
+        assert!(storage.retrieve_collection("cfr", &JsonCodec {}).is_err());
+    }
+
+    #[test]
+    fn test_least_recently_used_are_evicted() {
+        let size = encode_collection(&JsonCodec {}, &collection("aaa", 10))
+            .unwrap()
+            .len();
+        let mut storage =
+            TieredStorage::new(Box::new(MemoryStorage::new())).memory_budget(2 * size);
+
+        storage
+            .store_collection("aaa", &collection("aaa", 10), &JsonCodec {})
+            .unwrap();
+        storage
+            .store_collection("bbb", &collection("bbb", 10), &JsonCodec {})
+            .unwrap();
+        storage.retrieve_collection("aaa", &JsonCodec {}).unwrap();
+        storage
+            .store_collection("ccc", &collection("ccc", 10), &JsonCodec {})
+            .unwrap();
+
+        assert_eq!(cached_keys(&storage), vec!["aaa", "ccc"]);
+        // Still available from the backend.
+        assert_eq!(
+            storage.retrieve_collection("bbb", &JsonCodec {}).unwrap(),
+            collection("bbb", 10)
+        );
+        assert_eq!(cached_keys(&storage), vec!["bbb", "ccc"]);
+
+        let mut storage = TieredStorage::new(Box::new(MemoryStorage::new())).memory_budget(10);
+        storage
+            .store_collection("aaa", &collection("aaa", 10), &JsonCodec {})
+            .unwrap();
+        assert!(cached_keys(&storage).is_empty());
+    }
+
+    #[test]
+    fn test_backend_with_individual_records() {
+        let mut storage =
+            TieredStorage::new(Box::new(RecordStorage::new(Box::new(MemoryStorage::new()))));
+        storage
+            .store_collection("cfr", &collection("cfr", 10), &JsonCodec {})
+            .unwrap();
+        storage.clear().unwrap();
+        assert!(storage.retrieve_collection("cfr", &JsonCodec {}).is_err());
+
+        storage
+            .store_collection("cfr", &collection("cfr", 10), &JsonCodec {})
+            .unwrap();
+        storage
+            .apply_changes(
+                "cfr",
+                &collection("cfr", 11),
+                &[Record::new(json!({"id": "a", "last_modified": 11}))],
+                &JsonCodec {},
+            )
+            .unwrap();
+
+        let backend = storage.backend.as_collection_storage().unwrap();
+        assert_eq!(
+            backend.retrieve_collection("cfr", &JsonCodec {}).unwrap(),
+            collection("cfr", 11)
+        );
+        assert_eq!(
+            storage
+                .retrieve_record("cfr", "a", &JsonCodec {})
+                .unwrap()
+                .unwrap()
+                .last_modified(),
+            11
+        );
+    }
+}
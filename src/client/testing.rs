@@ -0,0 +1,18 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use super::{Collection, Record};
+use serde_json::json;
+
+/// A signed collection of the `main` bucket, as stored after a synchronization.
+pub(crate) fn test_collection(cid: &str, timestamp: u64, records: Vec<Record>) -> Collection {
+    Collection {
+        bid: "main".to_owned(),
+        cid: cid.to_owned(),
+        metadata: json!({"signature": {"x5u": "https://x5u"}}),
+        records,
+        timestamp,
+        signer: "signer".to_owned(),
+    }
+}
@@ -36,11 +36,29 @@
 #[macro_use]
 extern crate derive_builder;
 
+/// Records a field of the current span, when the `tracing` feature is enabled.
+macro_rules! record_span_field {
+    ($field:expr, $value:expr) => {
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record($field, $value);
+        #[cfg(not(feature = "tracing"))]
+        let _ = ($field, $value);
+    };
+}
+
 pub mod client;
 
+pub use client::Alert;
+pub use client::AlertHandler;
 pub use client::Client;
+pub use client::ClientConfig;
+pub use client::Codec;
 pub use client::Collection;
+pub use client::CorruptionHandler;
+pub use client::HttpClient;
+pub use client::Metrics;
 pub use client::Record;
+pub use client::ServerInfo;
 pub use client::SignatureError;
 pub use client::Storage;
 pub use client::StorageError;